@@ -30,6 +30,33 @@ pub fn get_account_id_from_seed<TPublic: Public>(seed: &str) -> AccountId where
 	AccountPublic::from(get_from_seed::<TPublic>(seed)).into_account()
 }
 
+/// Well-known dev accounts, tried in order when picking a default coinbase
+const DEV_SEEDS: [&str; 6] = ["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"];
+
+/// Max derived dev accounts tried when no well-known one is on the shard
+const MAX_DERIVED_DEV_SEEDS: u16 = 1024;
+
+/// Shard an account belongs to, the low bits of its last two bytes,
+/// same as `yp_sharding::utils::shard_num_for`
+fn shard_num_for(account: &AccountId, shard_count: u16) -> Option<u16> {
+	if !shard_count.is_power_of_two() {
+		return None;
+	}
+	let bytes: &[u8] = account.as_ref();
+	let a = u16::from_be_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]);
+	Some(a & (shard_count - 1))
+}
+
+/// Default coinbase for dev mode: a dev account that belongs to `shard_num`,
+/// so that mining rewards are spendable on the node's shard
+pub fn dev_coinbase(shard_num: u16, shard_count: u16) -> Option<AccountId> {
+	let well_known = DEV_SEEDS.iter().map(|seed| seed.to_string());
+	let derived = (0..MAX_DERIVED_DEV_SEEDS).map(|i| format!("Alice//coinbase//{}", i));
+	well_known.chain(derived)
+		.map(|seed| get_account_id_from_seed::<sr25519::Public>(&seed))
+		.find(|account| shard_num_for(account, shard_count) == Some(shard_num))
+}
+
 /// Helper function to generate an authority key for Aura
 pub fn authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
 	(
@@ -122,3 +149,19 @@ fn testnet_genesis(initial_authorities: Vec<(AuraId, GrandpaId)>,
 		}),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_dev_coinbase_on_shard() {
+		for &shard_count in &[2u16, 4] {
+			for shard_num in 0..shard_count {
+				let coinbase = dev_coinbase(shard_num, shard_count).expect("dev coinbase found");
+				assert_eq!(shard_num_for(&coinbase, shard_count), Some(shard_num));
+			}
+		}
+		assert_eq!(dev_coinbase(0, 3), None);
+	}
+}
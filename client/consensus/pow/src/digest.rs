@@ -20,11 +20,12 @@
 //! Implements POW signature wrapped in block header DigestItem.
 
 use sp_runtime::{
+    ConsensusEngineId,
     codec::{
         Decode, Encode,
     },
     generic::{DigestItem, OpaqueDigestItemId},
-    traits::Block,
+    traits::{Block, Header},
 };
 
 use yp_consensus_pow::YEE_POW_ENGINE_ID;
@@ -34,21 +35,187 @@ use super::PowSeal;
 /// Digest item acts as a valid POW consensus digest.
 pub trait CompatibleDigestItem<B: Block, AuthorityId: Decode + Encode + Clone>: Sized {
     /// construct digest item with work proof
-    fn pow_seal(seal: PowSeal<B, AuthorityId>) -> Self;
+    fn pow_seal(seal: PowSeal<B, AuthorityId>) -> Self {
+        Self::pow_seal_with_id(YEE_POW_ENGINE_ID, seal)
+    }
 
     /// get work proof if digest item is pow item
-    fn as_pow_seal(&self) -> Option<PowSeal<B, AuthorityId>>;
+    fn as_pow_seal(&self) -> Option<PowSeal<B, AuthorityId>> {
+        self.as_pow_seal_with_id(YEE_POW_ENGINE_ID)
+    }
+
+    /// construct digest item with work proof under given engine id
+    fn pow_seal_with_id(engine_id: ConsensusEngineId, seal: PowSeal<B, AuthorityId>) -> Self;
+
+    /// get work proof if digest item is pow item of given engine id
+    fn as_pow_seal_with_id(&self, engine_id: ConsensusEngineId) -> Option<PowSeal<B, AuthorityId>>;
 }
 
 impl<B, Hash, AuthorityId> CompatibleDigestItem<B, AuthorityId> for DigestItem<Hash> where
     B: Block,
     AuthorityId: Decode + Encode + Clone,
 {
-    fn pow_seal(seal: PowSeal<B, AuthorityId>) -> Self {
-        DigestItem::Consensus(YEE_POW_ENGINE_ID, seal.encode())
+    fn pow_seal_with_id(engine_id: ConsensusEngineId, seal: PowSeal<B, AuthorityId>) -> Self {
+        DigestItem::Consensus(engine_id, encode_pow_seal(&seal))
     }
 
-    fn as_pow_seal(&self) -> Option<PowSeal<B, AuthorityId>> {
-        self.try_to(OpaqueDigestItemId::Seal(&YEE_POW_ENGINE_ID))
+    fn as_pow_seal_with_id(&self, engine_id: ConsensusEngineId) -> Option<PowSeal<B, AuthorityId>> {
+        self.try_to(OpaqueDigestItemId::Consensus(&engine_id))
+    }
+}
+
+/// Encode seal as carried in the `Consensus` digest item of a header, for pool software.
+///
+/// Layout is SCALE encoding of the fields in order:
+/// - `authority_id`: encoding of the authority id
+/// - `pow_target`: 32 bytes, little endian U256
+/// - `timestamp`: 8 bytes, little endian u64
+/// - `work_proof`: 1 byte variant index (0 unknown, 1 nonce, 2 multi) followed by the proof
+/// - `relay_proof`: 32 bytes hash
+pub fn encode_pow_seal<B, AuthorityId>(seal: &PowSeal<B, AuthorityId>) -> Vec<u8> where
+    B: Block,
+    AuthorityId: Decode + Encode + Clone,
+{
+    seal.encode()
+}
+
+/// Decode seal encoded by `encode_pow_seal`, `None` if malformed or followed by extra bytes
+pub fn decode_pow_seal<B, AuthorityId>(bytes: &[u8]) -> Option<PowSeal<B, AuthorityId>> where
+    B: Block,
+    AuthorityId: Decode + Encode + Clone,
+{
+    let mut input = bytes;
+    let seal = PowSeal::decode(&mut input).ok()?;
+    if !input.is_empty() {
+        return None;
+    }
+    Some(seal)
+}
+
+/// Engine id of the pre-runtime digest carrying the client version of the miner
+pub const CLIENT_VERSION_ENGINE_ID: ConsensusEngineId = *b"YeeV";
+
+/// Max bytes of client version tag, longer versions are truncated
+pub const MAX_CLIENT_VERSION_LEN: usize = 32;
+
+/// Digest item carries the client version a block is mined with.
+pub trait ClientVersionDigestItem: Sized {
+    /// construct digest item with client version
+    fn client_version(version: &str) -> Self;
+
+    /// get client version if digest item is client version item
+    fn as_client_version(&self) -> Option<String>;
+}
+
+impl<Hash> ClientVersionDigestItem for DigestItem<Hash> {
+    fn client_version(version: &str) -> Self {
+        let mut len = version.len().min(MAX_CLIENT_VERSION_LEN);
+        while !version.is_char_boundary(len) {
+            len -= 1;
+        }
+        DigestItem::PreRuntime(CLIENT_VERSION_ENGINE_ID, version[..len].as_bytes().to_vec())
+    }
+
+    fn as_client_version(&self) -> Option<String> {
+        match self {
+            DigestItem::PreRuntime(id, data) if *id == CLIENT_VERSION_ENGINE_ID && data.len() <= MAX_CLIENT_VERSION_LEN =>
+                String::from_utf8(data.clone()).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Client version a header is mined with, if tagged
+pub fn client_version_of<H: Header>(header: &H) -> Option<String> {
+    header.digest().logs().iter().find_map(ClientVersionDigestItem::as_client_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use sp_core::H256;
+    use sp_runtime::traits::Header as HeaderT;
+    use yee_runtime::{Block, DigestItem, Header};
+
+    use crate::{PowSeal, WorkProof};
+
+    use super::*;
+
+    #[test]
+    fn test_custom_engine_id() {
+        let seal = PowSeal::<Block, u64> {
+            authority_id: 1,
+            pow_target: 2.into(),
+            timestamp: 3,
+            work_proof: WorkProof::Unknown,
+            relay_proof: H256::default(),
+        };
+        let custom_id = *b"Pvt!";
+
+        let item = <DigestItem as CompatibleDigestItem<Block, u64>>::pow_seal(seal.clone());
+        let read: Option<PowSeal<Block, u64>> = item.as_pow_seal();
+        assert_eq!(read.map(|x| x.timestamp), Some(3));
+
+        let item = <DigestItem as CompatibleDigestItem<Block, u64>>::pow_seal_with_id(custom_id, seal);
+        let read: Option<PowSeal<Block, u64>> = item.as_pow_seal_with_id(custom_id);
+        assert_eq!(read.map(|x| x.timestamp), Some(3));
+        let read: Option<PowSeal<Block, u64>> = item.as_pow_seal();
+        assert!(read.is_none());
+    }
+
+    #[test]
+    fn test_encode_pow_seal() {
+        let seal = PowSeal::<Block, u64> {
+            authority_id: 1,
+            pow_target: 2.into(),
+            timestamp: 3,
+            work_proof: WorkProof::Unknown,
+            relay_proof: [4u8; 32].into(),
+        };
+
+        let bytes = encode_pow_seal(&seal);
+        assert_eq!(bytes.len(), 8 + 32 + 8 + 1 + 32);
+        assert_eq!(&bytes[40..48], &3u64.to_le_bytes());
+        assert_eq!(bytes[48], 0);
+
+        let decoded: PowSeal<Block, u64> = decode_pow_seal(&bytes).unwrap();
+        assert_eq!(decoded.encode(), bytes);
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode_pow_seal::<Block, u64>(&trailing).is_none());
+        assert!(decode_pow_seal::<Block, u64>(&bytes[..10]).is_none());
+
+        // same bytes as the seal extracted from a header
+        let mut header = Header::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+        header.digest_mut().push(<DigestItem as CompatibleDigestItem<Block, u64>>::pow_seal(seal));
+        let item = header.digest().logs().last().unwrap();
+        let read: PowSeal<Block, u64> = item.as_pow_seal().unwrap();
+        assert_eq!(encode_pow_seal(&read), bytes);
+        match item {
+            DigestItem::Consensus(id, data) => {
+                assert_eq!(*id, YEE_POW_ENGINE_ID);
+                assert_eq!(*data, bytes);
+            }
+            _ => panic!("seal is not a consensus digest item"),
+        }
+    }
+
+    #[test]
+    fn test_client_version() {
+        let mut header = Header::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+        assert_eq!(client_version_of(&header), None);
+
+        header.digest_mut().push(DigestItem::client_version("yee-node 0.4.0"));
+        assert_eq!(client_version_of(&header), Some("yee-node 0.4.0".to_string()));
+
+        // size bounded
+        let long = "v".repeat(MAX_CLIENT_VERSION_LEN + 10);
+        let item = DigestItem::client_version(&long);
+        assert_eq!(item.as_client_version().map(|v| v.len()), Some(MAX_CLIENT_VERSION_LEN));
+        let oversized = DigestItem::PreRuntime(CLIENT_VERSION_ENGINE_ID, long.into_bytes());
+        assert_eq!(oversized.as_client_version(), None);
+
+        // other pre-runtime digests are not versions
+        assert_eq!(DigestItem::PreRuntime(*b"aura", b"1.0".to_vec()).as_client_version(), None);
     }
 }
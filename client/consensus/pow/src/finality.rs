@@ -0,0 +1,161 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Finality proof import guard for POW import queue
+
+use std::collections::{HashSet, VecDeque};
+
+use log::debug;
+use sp_consensus::import_queue::{BoxFinalityProofImport, FinalityProofImport, Verifier};
+use sp_runtime::traits::{Block, NumberFor};
+
+/// Number of recently imported finality proofs remembered for dedup
+pub const FINALITY_PROOF_DEDUP_CAPACITY: usize = 1024;
+
+/// Wraps a finality proof import and skips proofs already imported for the same block hash.
+///
+/// The same proof is commonly received from several peers.
+pub struct DedupFinalityProofImport<B: Block> {
+	inner: BoxFinalityProofImport<B>,
+	imported: HashSet<B::Hash>,
+	order: VecDeque<B::Hash>,
+	capacity: usize,
+}
+
+impl<B: Block> DedupFinalityProofImport<B> {
+	pub fn new(inner: BoxFinalityProofImport<B>) -> Self {
+		Self::with_capacity(inner, FINALITY_PROOF_DEDUP_CAPACITY)
+	}
+
+	pub fn with_capacity(inner: BoxFinalityProofImport<B>, capacity: usize) -> Self {
+		Self {
+			inner,
+			imported: HashSet::new(),
+			order: VecDeque::new(),
+			capacity,
+		}
+	}
+
+	fn remember(&mut self, hash: B::Hash) {
+		if self.imported.insert(hash) {
+			self.order.push_back(hash);
+		}
+		while self.order.len() > self.capacity {
+			if let Some(old) = self.order.pop_front() {
+				self.imported.remove(&old);
+			}
+		}
+	}
+}
+
+impl<B: Block> FinalityProofImport<B> for DedupFinalityProofImport<B> {
+	type Error = sp_consensus::Error;
+
+	fn on_start(&mut self) -> Vec<(B::Hash, NumberFor<B>)> {
+		self.inner.on_start()
+	}
+
+	fn import_finality_proof(
+		&mut self,
+		hash: B::Hash,
+		number: NumberFor<B>,
+		finality_proof: Vec<u8>,
+		verifier: &mut dyn Verifier<B>,
+	) -> Result<(B::Hash, NumberFor<B>), Self::Error> {
+		if self.imported.contains(&hash) {
+			debug!("finality proof for {:?} already imported, skipped", hash);
+			return Ok((hash, number));
+		}
+
+		let result = self.inner.import_finality_proof(hash, number, finality_proof, verifier)?;
+		self.remember(hash);
+		Ok(result)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+	use sp_blockchain::well_known_cache_keys::Id as CacheKeyId;
+	use sp_consensus::{BlockImportParams, BlockOrigin};
+	use sp_runtime::Justification;
+	use sp_runtime::traits::Block as BlockT;
+	use yee_runtime::Block;
+
+	use super::*;
+
+	struct CountingImport(Arc<AtomicUsize>);
+
+	impl FinalityProofImport<Block> for CountingImport {
+		type Error = sp_consensus::Error;
+
+		fn import_finality_proof(
+			&mut self,
+			hash: <Block as BlockT>::Hash,
+			number: NumberFor<Block>,
+			_finality_proof: Vec<u8>,
+			_verifier: &mut dyn Verifier<Block>,
+		) -> Result<(<Block as BlockT>::Hash, NumberFor<Block>), Self::Error> {
+			self.0.fetch_add(1, Ordering::SeqCst);
+			Ok((hash, number))
+		}
+	}
+
+	struct NoopVerifier;
+
+	impl Verifier<Block> for NoopVerifier {
+		fn verify(
+			&mut self,
+			_origin: BlockOrigin,
+			_header: <Block as BlockT>::Header,
+			_justification: Option<Justification>,
+			_body: Option<Vec<<Block as BlockT>::Extrinsic>>,
+		) -> Result<(BlockImportParams<Block, ()>, Option<Vec<(CacheKeyId, Vec<u8>)>>), String> {
+			unimplemented!()
+		}
+	}
+
+	#[test]
+	fn test_duplicate_finality_proof_skipped() {
+		let count = Arc::new(AtomicUsize::new(0));
+		let mut import = DedupFinalityProofImport::<Block>::new(Box::new(CountingImport(count.clone())));
+		let hash = [1u8; 32].into();
+
+		assert_eq!(import.import_finality_proof(hash, 1, vec![1], &mut NoopVerifier).ok(), Some((hash, 1)));
+		assert_eq!(import.import_finality_proof(hash, 1, vec![1], &mut NoopVerifier).ok(), Some((hash, 1)));
+		assert_eq!(count.load(Ordering::SeqCst), 1);
+
+		let other = [2u8; 32].into();
+		assert!(import.import_finality_proof(other, 2, vec![2], &mut NoopVerifier).is_ok());
+		assert_eq!(count.load(Ordering::SeqCst), 2);
+	}
+
+	#[test]
+	fn test_dedup_capacity() {
+		let count = Arc::new(AtomicUsize::new(0));
+		let mut import = DedupFinalityProofImport::<Block>::with_capacity(Box::new(CountingImport(count.clone())), 1);
+		let first = [1u8; 32].into();
+		let second = [2u8; 32].into();
+
+		assert!(import.import_finality_proof(first, 1, vec![], &mut NoopVerifier).is_ok());
+		assert!(import.import_finality_proof(second, 2, vec![], &mut NoopVerifier).is_ok());
+		// first one was evicted and is imported again
+		assert!(import.import_finality_proof(first, 1, vec![], &mut NoopVerifier).is_ok());
+		assert_eq!(count.load(Ordering::SeqCst), 3);
+	}
+}
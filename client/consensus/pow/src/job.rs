@@ -42,7 +42,7 @@ use {
 	sp_consensus::{
 		BlockImport, BlockImportParams, BlockOrigin, Environment, ForkChoiceStrategy, Proposer,
 	},
-	sp_inherents::InherentDataProviders,
+	sp_inherents::{InherentData, InherentDataProviders},
 	sp_runtime::{
 		traits::{Block, DigestItemFor, NumberFor, Header},
 	},
@@ -57,7 +57,8 @@ use yp_consensus_pow::YeePOWApi;
 use yp_context::Context;
 
 use crate::{CompatibleDigestItem, PowSeal, ShardExtra, WorkProof};
-use crate::pow::{calc_pow_target, check_work_proof, gen_extrinsic_proof};
+use crate::digest::ClientVersionDigestItem;
+use crate::pow::{calc_pow_target, check_extrinsics_root, check_work_proof, gen_extrinsic_proof};
 use crate::verifier::check_scale;
 use parking_lot::Mutex;
 use sp_consensus::{SelectChain, RecordProof};
@@ -110,6 +111,8 @@ pub struct DefaultJobManager<B, C, SC, E, AccountId, AuthorityId, I> where
 	block_import: Arc<Mutex<I>>,
 	shard_extra: ShardExtra<AccountId>,
 	context: Context<B>,
+	/// client version tagged into mined blocks, if any
+	client_version: Option<String>,
 	phantom: PhantomData<B>,
 }
 
@@ -140,9 +143,16 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> DefaultJobManager<B, C, SC, E, Acco
 			block_import,
 			shard_extra,
 			context,
+			client_version: None,
 			phantom: PhantomData,
 		}
 	}
+
+	/// Tag mined blocks with the client version, e.g. `impl_version` of the cli
+	pub fn with_client_version(mut self, client_version: Option<String>) -> Self {
+		self.client_version = client_version;
+		self
+	}
 }
 
 impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B, C, SC, E, AccountId, AuthorityId, I>
@@ -174,6 +184,13 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B,
 			Err(e) => return Box::new(future::err(e)),
 		};
 
+		let timestamp = match job_timestamp(&inherent_data) {
+			Ok(timestamp) => timestamp,
+			Err(e) => return Box::new(future::err(e)),
+		};
+
+		let inherent_digests = inherent_digests::<B>(self.client_version.as_ref().map(String::as_str));
+
 		let awaiting_proposer = self.env.init(&chain_head);
 
 		let client = self.client.clone();
@@ -184,12 +201,14 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B,
 			let (header, body) = block.deconstruct();
 			let header_num = header.number().clone();
 			let header_pre_hash = header.hash();
-			let timestamp = timestamp_now()?;
 			let pow_target = calc_pow_target(client, &header, timestamp, &context)?;
 			let authority_id = authority_id;
 			let work_proof = WorkProof::Unknown;
+			// body must not diverge from the header it was built with
+			check_extrinsics_root::<B>(&header, &body).map_err(to_common_error)?;
 			// generate proof
-			let (relay_proof, proof) = gen_extrinsic_proof::<B>(&header, &body);
+			let (relay_proof, proof) = gen_extrinsic_proof::<B>(&header, &body, context.max_proof_extrinsics)
+				.map_err(to_common_error)?;
 
 			let pow_seal = PowSeal {
 				authority_id,
@@ -199,7 +218,7 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B,
 				relay_proof,
 			};
 			let mut header_with_pow_seal = header.clone();
-			let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal(pow_seal.clone());
+			let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal_with_id(context.pow_engine_id, pow_seal.clone());
 			header_with_pow_seal.digest_mut().push(item);
 
 			let hash = header_with_pow_seal.hash();
@@ -217,7 +236,7 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B,
 
 		awaiting_proposer.and_then(move |mut proposer| proposer.propose(
 			inherent_data,
-			Digest::default(),
+			inherent_digests,
 			Duration::from_secs(10),
 			RecordProof::No,
 		)).and_then(build_job)
@@ -232,7 +251,7 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B,
 
 		let check_job = move |job: Self::Job| -> Result<<Self::Job as Job>::Hash, sp_consensus::Error>{
 			let number = &job.header.number().clone();
-			let (post_digest, hash) = check_work_proof(&job.header, &job.digest_item)?;
+			let (post_digest, hash) = check_work_proof(&job.header, &job.digest_item, self.context.pow_engine_id)?;
 
 			check_scale::<B, AccountId>(&job.header, self.shard_extra.clone())?;
 
@@ -251,7 +270,56 @@ impl<B, C, SC, E, AccountId, AuthorityId, I> JobManager for DefaultJobManager<B,
 	}
 }
 
+/// Pre-runtime digests of a job, tagged with client version if any
+fn inherent_digests<B: Block>(client_version: Option<&str>) -> Digest<B::Hash> {
+	let mut digest = Digest::default();
+	if let Some(version) = client_version {
+		digest.push(<DigestItemFor<B> as ClientVersionDigestItem>::client_version(version));
+	}
+	digest
+}
+
+/// Timestamp from the registered timestamp inherent data provider,
+/// falls back to system clock if not provided
+fn job_timestamp(inherent_data: &InherentData) -> Result<u64, sp_consensus::Error> {
+	match inherent_data.get_data::<sp_timestamp::InherentType>(&sp_timestamp::INHERENT_IDENTIFIER) {
+		Ok(Some(timestamp)) => Ok(timestamp),
+		Ok(None) => {
+			warn!("timestamp inherent data not provided, use system clock");
+			timestamp_now()
+		}
+		Err(e) => Err(to_common_error(e)),
+	}
+}
+
 fn timestamp_now() -> Result<u64, sp_consensus::Error> {
 	Ok(SystemTime::now().duration_since(UNIX_EPOCH)
 		.map_err(to_common_error)?.as_millis() as u64)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_job_timestamp() {
+		let mut inherent_data = InherentData::new();
+		inherent_data.put_data(sp_timestamp::INHERENT_IDENTIFIER, &1_500_000_000_000u64).unwrap();
+		assert_eq!(job_timestamp(&inherent_data).ok(), Some(1_500_000_000_000));
+
+		// fallback to system clock
+		let before = timestamp_now().unwrap();
+		let timestamp = job_timestamp(&InherentData::new()).unwrap();
+		assert!(timestamp >= before);
+	}
+
+	#[test]
+	fn test_inherent_digests() {
+		let untagged = inherent_digests::<yee_runtime::Block>(None);
+		assert!(untagged.logs().is_empty());
+
+		let tagged = inherent_digests::<yee_runtime::Block>(Some("yee-node 0.4.0"));
+		let version = tagged.logs().iter().find_map(ClientVersionDigestItem::as_client_version);
+		assert_eq!(version, Some("yee-node 0.4.0".to_string()));
+	}
+}
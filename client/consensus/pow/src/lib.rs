@@ -21,12 +21,13 @@ use sp_consensus::{BlockImport, Environment, SyncOracle, SelectChain, Proposer};
 use sp_consensus::import_queue::{BasicQueue, BoxFinalityProofImport, BoxJustificationImport};
 use sp_core::crypto::Pair;
 use sp_core::H256;
+use sp_core::hexdisplay::HexDisplay;
 
 use {
 	futures::Future,
 	log::warn,
 	parking_lot::RwLock,
-	std::{fmt::Debug, marker::PhantomData, sync::Arc},
+	std::{fmt::Debug, marker::PhantomData, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration},
 };
 use {
 	// foreign_chain::{ForeignChain, ForeignChainConfig},
@@ -37,21 +38,27 @@ use {
 		codec::{
 			Codec, Decode, Encode,
 		},
+		generic::BlockId,
 		traits::{
 			Block,
 			DigestItemFor,
+			Header,
 			NumberFor,
 		},
 	},
-	yp_sharding::ShardingAPI,
+	yp_sharding::{ShardingAPI, utils::shard_num_for},
 };
 use {
 	yp_consensus_pow::YeePOWApi,
 };
-pub use digest::CompatibleDigestItem;
+pub use digest::{client_version_of, ClientVersionDigestItem, CompatibleDigestItem, decode_pow_seal, encode_pow_seal};
+pub use finality::DedupFinalityProofImport;
 pub use job::{DefaultJob, DefaultJobManager, JobManager};
-pub use pow::{CompactMerkleProof, MiningAlgorithm, MiningHash, OriginalMerkleProof,
-			  PowSeal, ProofMulti, ProofNonce, WorkProof};
+pub use pow::{CompactMerkleProof, DEFAULT_MAX_PROOF_EXTRINSICS, MiningAlgorithm, MiningHash, OriginalMerkleProof,
+			  NONCE_PREFIX, PowSeal, ProofMulti, ProofNonce, RelayRejectReason, work_hash, WorkProof};
+pub use revert::{ScaleOutRevertWatch, ShardConfigStore, watch_best_headers};
+pub use verifier::{check_block_relay, DigestLimit, ImportError, ImportErrorCounts, relay_reward_condition, verify_header_chain, VerifyError};
+pub use worker::{DEFAULT_JOB_REFRESH_INTERVAL, DEFAULT_MINING_ITERATIONS, NonceStrategy, SequentialNonce};
 use yc_sharding::{ScaleOutPhaseDigestItem, ShardingDigestItem};
 use yp_consensus_pow::RewardCondition;
 use yp_context::Context;
@@ -60,7 +67,9 @@ use parking_lot::Mutex;
 
 mod job;
 mod digest;
+mod finality;
 mod pow;
+mod revert;
 mod verifier;
 mod worker;
 
@@ -71,6 +80,18 @@ pub struct Params<AccountId, B> where
 	pub mine: bool,
 	pub shard_extra: ShardExtra<AccountId>,
 	pub context: Context<B>,
+	/// mining is reported stalled after this many target block times without a finished job
+	pub stall_multiplier: u32,
+	/// restart the service when mining stalls
+	pub restart_on_stall: bool,
+	/// follow shard count of best block instead of refusing to start on mismatch
+	pub auto_correct_shard_count: bool,
+	/// nonces tried per job, see `DEFAULT_MINING_ITERATIONS`
+	pub mining_iterations: u64,
+	/// wait before retrying while major syncing, see `DEFAULT_JOB_REFRESH_INTERVAL`
+	pub job_refresh_interval: Duration,
+	/// client version tagged into mined blocks, e.g. `impl_version` of the cli
+	pub client_version: Option<String>,
 }
 
 pub fn start_pow<B, P, C, SC, I, E, AccountId, SO, OnExit>(
@@ -101,6 +122,12 @@ pub fn start_pow<B, P, C, SC, I, E, AccountId, SO, OnExit>(
 	DigestItemFor<B>: CompatibleDigestItem<B, P::Public> + ShardingDigestItem<u16> + ScaleOutPhaseDigestItem<NumberFor<B>, u16>,
 	B::Hash: From<H256> + Ord,
 {
+	check_coinbase(&params.shard_extra.coinbase)?;
+	check_stall_multiplier(params.stall_multiplier)?;
+	check_mining_params(params.mining_iterations, params.job_refresh_interval)?;
+	let mut params = params;
+	check_shard_count(client.as_ref(), &mut params.shard_extra, params.auto_correct_shard_count)?;
+
 	let block_import = Arc::new(Mutex::new(block_import));
 	let inner_job_manager = Arc::new(DefaultJobManager::new(
 		client.clone(),
@@ -111,7 +138,7 @@ pub fn start_pow<B, P, C, SC, I, E, AccountId, SO, OnExit>(
 		block_import.clone(),
 		params.shard_extra.clone(),
 		params.context.clone(),
-	));
+	).with_client_version(params.client_version.clone()));
 
 	let mut reg_lock = job_manager.write();
 	match *reg_lock {
@@ -129,12 +156,22 @@ pub fn start_pow<B, P, C, SC, I, E, AccountId, SO, OnExit>(
 		block_import,
 		inherent_data_providers.clone(),
 		params.shard_extra.clone(),
+		params.context.pow_engine_id,
+	));
+	let watchdog = Arc::new(worker::StallWatchdog::new(
+		Duration::from_secs(params.context.genesis_target_block_time),
+		params.stall_multiplier,
+		params.restart_on_stall,
+		params.shard_extra.trigger_exit.clone(),
 	));
 	worker::start_worker(
 		worker,
 		sync_oracle,
 		on_exit,
-		params.mine)
+		params.mine,
+		params.mining_iterations,
+		params.job_refresh_interval,
+		watchdog)
 }
 
 /// POW chain import queue
@@ -146,6 +183,40 @@ pub trait TriggerExit: Send + Sync {
 	fn trigger_stop(&self);
 }
 
+/// Forwards only the first restart and the first stop to `inner`.
+///
+/// Worker and verifier both see the scale out `Committed` block,
+/// `ShardExtra::new` wraps the service trigger once so clones of it restart the service once.
+pub struct OnceTriggerExit {
+	inner: Arc<dyn TriggerExit>,
+	restarted: AtomicBool,
+	stopped: AtomicBool,
+}
+
+impl OnceTriggerExit {
+	pub fn new(inner: Arc<dyn TriggerExit>) -> Self {
+		OnceTriggerExit {
+			inner,
+			restarted: AtomicBool::new(false),
+			stopped: AtomicBool::new(false),
+		}
+	}
+}
+
+impl TriggerExit for OnceTriggerExit {
+	fn trigger_restart(&self) {
+		if !self.restarted.swap(true, Ordering::SeqCst) {
+			self.inner.trigger_restart();
+		}
+	}
+
+	fn trigger_stop(&self) {
+		if !self.stopped.swap(true, Ordering::SeqCst) {
+			self.inner.trigger_stop();
+		}
+	}
+}
+
 #[derive(Clone)]
 pub struct ShardExtra<AccountId> {
 	pub coinbase: AccountId,
@@ -155,6 +226,26 @@ pub struct ShardExtra<AccountId> {
 	pub trigger_exit: Arc<dyn TriggerExit>,
 }
 
+impl<AccountId> ShardExtra<AccountId> {
+	/// Wrap `trigger_exit` in `OnceTriggerExit`,
+	/// pass clones of the result to both `start_pow` and `import_queue`.
+	pub fn new(
+		coinbase: AccountId,
+		shard_num: u16,
+		shard_count: u16,
+		scale_out: Option<ScaleOut<u16>>,
+		trigger_exit: Arc<dyn TriggerExit>,
+	) -> Self {
+		ShardExtra {
+			coinbase,
+			shard_num,
+			shard_count,
+			scale_out,
+			trigger_exit: Arc::new(OnceTriggerExit::new(trigger_exit)),
+		}
+	}
+}
+
 /// Start import queue for POW consensus
 pub fn import_queue<B, I, C, S, AccountId, AuthorityId>(
 	block_import: I,
@@ -165,19 +256,23 @@ pub fn import_queue<B, I, C, S, AccountId, AuthorityId>(
 	// foreign_chains: Arc<RwLock<Option<ForeignChain<F>>>>,
 	shard_extra: ShardExtra<AccountId>,
 	context: Context<B>,
+	digest_limit: DigestLimit,
+	import_errors: Arc<ImportErrorCounts>,
 	spawner: &S,
 ) -> Result<PowImportQueue<B, sp_api::TransactionFor<C, B>>, sp_consensus::Error> where
 	B: Block,
 	H256: From<B::Hash>,
+	B::Hash: From<H256> + Ord,
 	DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16> + ScaleOutPhaseDigestItem<NumberFor<B>, u16>,
 	C: ProvideRuntimeApi<B> + 'static + Send + Sync,
 	C: HeaderBackend<B>,
-	C::Api: ShardingAPI<B> + YeePOWApi<B>,
+	C::Api: ShardingAPI<B> + YeePOWApi<B> + sp_block_builder::BlockBuilder<B>,
 	AccountId: Codec + Send + Sync + Clone + Default + 'static,
 	AuthorityId: Decode + Encode + Clone + Send + Sync + 'static,
 	I: BlockImport<B, Error=sp_consensus::Error, Transaction=sp_api::TransactionFor<C, B>> + Send + Sync + 'static,
 	S: sp_core::traits::SpawnBlocking,
 {
+	check_coinbase(&shard_extra.coinbase)?;
 	register_inherent_data_provider(&inherent_data_providers, shard_extra.coinbase.clone())?;
 
 	let verifier = verifier::PowVerifier {
@@ -187,7 +282,12 @@ pub fn import_queue<B, I, C, S, AccountId, AuthorityId>(
 		phantom: PhantomData,
 		shard_extra,
 		context,
+		digest_limit,
+		import_errors,
 	};
+	let finality_proof_import = finality_proof_import
+		.map(|import| Box::new(DedupFinalityProofImport::new(import)) as BoxFinalityProofImport<B>);
+
 	Ok(BasicQueue::new(
 		verifier,
 		Box::new(block_import),
@@ -210,6 +310,162 @@ pub fn register_inherent_data_provider<AccountId: 'static + Codec + Send + Sync>
 	}
 }
 
+/// Compare configured shard count against `ShardMarker` of best block.
+///
+/// A node that missed a scale out would mis-route silently, so it is refused to start,
+/// or with `auto_correct` switched to the chain shard count and the shard of its coinbase.
+pub fn check_shard_count<B, C, AccountId>(
+	client: &C,
+	shard_extra: &mut ShardExtra<AccountId>,
+	auto_correct: bool,
+) -> Result<(), sp_consensus::Error> where
+	B: Block,
+	C: HeaderBackend<B>,
+	AccountId: Encode + Decode,
+	DigestItemFor<B>: ShardingDigestItem<u16>,
+{
+	let best_hash = client.info().best_hash;
+	let best_header = client.header(BlockId::Hash(best_hash))
+		.map_err(|e| sp_consensus::Error::ClientImport(format!("{:?}", e)))?;
+	let chain_shard_info = best_header.and_then(|header| header.digest().logs().iter().rev()
+		.filter_map(ShardingDigestItem::as_sharding_info)
+		.next());
+	match chain_shard_info {
+		Some((_, chain_shard_count)) => reconcile_shard_count(shard_extra, chain_shard_count, auto_correct),
+		// genesis carries no shard marker
+		None => Ok(()),
+	}
+}
+
+fn reconcile_shard_count<AccountId: Encode + Decode>(
+	shard_extra: &mut ShardExtra<AccountId>,
+	chain_shard_count: u16,
+	auto_correct: bool,
+) -> Result<(), sp_consensus::Error> {
+	if shard_extra.shard_count == chain_shard_count {
+		return Ok(());
+	}
+	if !auto_correct {
+		return Err(sp_consensus::Error::ClientImport(format!(
+			"Shard count mismatch, node: {}, chain: {}", shard_extra.shard_count, chain_shard_count
+		)));
+	}
+	let shard_num = shard_num_for(&shard_extra.coinbase, chain_shard_count).ok_or_else(|| {
+		sp_consensus::Error::ClientImport(format!("Invalid chain shard count: {}", chain_shard_count))
+	})?;
+	warn!(
+		"Shard count mismatch, node: {}, chain: {}, switch to shard {}",
+		shard_extra.shard_count, chain_shard_count, shard_num
+	);
+	shard_extra.shard_num = shard_num;
+	shard_extra.shard_count = chain_shard_count;
+	shard_extra.scale_out = None;
+	Ok(())
+}
+
+/// Reject a coinbase that would make rewards unspendable, e.g. a zero account from a malformed arg
+pub fn check_coinbase<AccountId: Encode>(coinbase: &AccountId) -> Result<(), sp_consensus::Error> {
+	let encoded = coinbase.encode();
+	if encoded.is_empty() || encoded.iter().all(|x| *x == 0) {
+		return Err(sp_consensus::Error::ClientImport(format!("Invalid coinbase: 0x{}", HexDisplay::from(&encoded))));
+	}
+	Ok(())
+}
+
+/// Reject a stall multiplier of 0, the zero timeout would report a stall on every tick
+pub fn check_stall_multiplier(stall_multiplier: u32) -> Result<(), sp_consensus::Error> {
+	if stall_multiplier == 0 {
+		return Err(sp_consensus::Error::ClientImport(format!("Invalid stall multiplier: {}", stall_multiplier)));
+	}
+	Ok(())
+}
+
+/// Reject zero mining iterations or refresh interval, the worker would spin without mining
+pub fn check_mining_params(mining_iterations: u64, job_refresh_interval: Duration) -> Result<(), sp_consensus::Error> {
+	if mining_iterations == 0 {
+		return Err(sp_consensus::Error::ClientImport(format!("Invalid mining iterations: {}", mining_iterations)));
+	}
+	if job_refresh_interval == Duration::from_secs(0) {
+		return Err(sp_consensus::Error::ClientImport(format!("Invalid job refresh interval: {:?}", job_refresh_interval)));
+	}
+	Ok(())
+}
+
 fn inherent_to_common_error(err: sp_inherents::Error) -> sp_consensus::Error {
 	sp_consensus::Error::InherentData(err).into()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct NoopExit;
+
+	impl TriggerExit for NoopExit {
+		fn trigger_restart(&self) {}
+
+		fn trigger_stop(&self) {}
+	}
+
+	fn shard_extra(shard_num: u16, shard_count: u16) -> ShardExtra<[u8; 32]> {
+		let mut coinbase = [0u8; 32];
+		// shard 1 of 2, shard 3 of 4
+		coinbase[31] = 0b11;
+		ShardExtra {
+			coinbase,
+			shard_num,
+			shard_count,
+			scale_out: None,
+			trigger_exit: Arc::new(NoopExit),
+		}
+	}
+
+	#[test]
+	fn test_shard_count_match() {
+		let mut extra = shard_extra(1, 2);
+		assert!(reconcile_shard_count(&mut extra, 2, false).is_ok());
+		assert_eq!((extra.shard_num, extra.shard_count), (1, 2));
+	}
+
+	#[test]
+	fn test_shard_count_mismatch_refused() {
+		let mut extra = shard_extra(1, 2);
+		assert!(reconcile_shard_count(&mut extra, 4, false).is_err());
+		assert_eq!((extra.shard_num, extra.shard_count), (1, 2));
+	}
+
+	#[test]
+	fn test_shard_count_mismatch_auto_corrected() {
+		let mut extra = shard_extra(1, 2);
+		assert!(reconcile_shard_count(&mut extra, 4, true).is_ok());
+		assert_eq!((extra.shard_num, extra.shard_count), (3, 4));
+
+		assert!(reconcile_shard_count(&mut extra, 3, true).is_err());
+	}
+
+	#[test]
+	fn test_check_coinbase() {
+		let mut coinbase = [0u8; 32];
+		coinbase.copy_from_slice(&hex::decode("8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48").unwrap());
+		assert!(check_coinbase(&coinbase).is_ok());
+
+		assert!(check_coinbase(&[0u8; 32]).is_err());
+	}
+
+	#[test]
+	fn test_check_stall_multiplier() {
+		assert!(check_stall_multiplier(1).is_ok());
+		assert!(check_stall_multiplier(3).is_ok());
+
+		assert!(check_stall_multiplier(0).is_err());
+	}
+
+	#[test]
+	fn test_check_mining_params() {
+		assert!(check_mining_params(DEFAULT_MINING_ITERATIONS, DEFAULT_JOB_REFRESH_INTERVAL).is_ok());
+		assert!(check_mining_params(1, Duration::from_millis(1)).is_ok());
+
+		assert!(check_mining_params(0, DEFAULT_JOB_REFRESH_INTERVAL).is_err());
+		assert!(check_mining_params(DEFAULT_MINING_ITERATIONS, Duration::from_secs(0)).is_err());
+	}
+}
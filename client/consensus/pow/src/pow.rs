@@ -17,7 +17,7 @@
 
 //! POW work proof used in block header digest
 use std::collections::hash_map::HashMap;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::hash::Hasher;
 use std::iter::FromIterator;
 use std::marker::PhantomData;
@@ -35,6 +35,7 @@ use sp_runtime::{
     codec::{
         Decode, Encode,
     },
+    ConsensusEngineId,
     generic::BlockId,
     Proof as ExtrinsicProof,
     traits::{BlakeTwo256, Block, DigestItemFor, Hash as HashT, Header,
@@ -54,6 +55,9 @@ use crate::CompatibleDigestItem;
 /// Max length in bytes for pow extra data
 pub const MAX_EXTRA_DATA_LENGTH: usize = 32;
 
+/// Prefix of nonce proof extra data, followed by shard num in little endian
+pub const NONCE_PREFIX: &str = "yeeroot-";
+
 /// POW consensus seal
 #[derive(Clone, Debug, Decode, Encode)]
 pub struct PowSeal<B: Block, AuthorityId: Decode + Encode + Clone> {
@@ -98,6 +102,25 @@ impl ProofNonce {
             nonce,
         }
     }
+
+    /// Nonce proof with `shard_num` embedded right after the prefix
+    pub fn get_with_shard(prefix: &str, shard_num: u16, extra_bytes: usize, nonce: u64) -> Self {
+        assert!(extra_bytes >= 2);
+        let mut proof = Self::get_with_prefix_len(prefix, extra_bytes, nonce);
+        proof.extra_data[prefix.len()..prefix.len() + 2].copy_from_slice(&shard_num.to_le_bytes());
+        proof
+    }
+
+    /// Shard num embedded after the prefix, `None` if extra data does not start with prefix
+    pub fn shard_num(&self, prefix: &str) -> Option<u16> {
+        let prefix = prefix.as_bytes();
+        if self.extra_data.len() < prefix.len() + 2 || !self.extra_data.starts_with(prefix) {
+            return None;
+        }
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(&self.extra_data[prefix.len()..prefix.len() + 2]);
+        Some(u16::from_le_bytes(bytes))
+    }
 }
 
 /// Multi-Mining pow proof with header-trie spv proof
@@ -114,10 +137,22 @@ pub struct ProofMulti<B: Block> {
     pub merkle_proof: Vec<B::Hash>,
 }
 
+/// Hash of the work header, i.e. `header` sealed with `seal` under `engine_id`
+pub fn work_hash<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, AuthorityId>, engine_id: ConsensusEngineId) -> B::Hash where
+    B: Block,
+    AuthorityId: Decode + Encode + Clone,
+    DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId>,
+{
+    let mut work_header = header.clone();
+    let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal_with_id(engine_id, seal.to_owned());
+    work_header.digest_mut().push(item);
+    work_header.hash()
+}
+
 /// Check proof
 ///
 /// Returns (post_digest, hash)
-pub fn check_work_proof<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, AuthorityId>) -> Result<(DigestItemFor<B>, B::Hash), String> where
+pub fn check_work_proof<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, AuthorityId>, engine_id: ConsensusEngineId) -> Result<(DigestItemFor<B>, B::Hash), String> where
     B: Block,
     AuthorityId: Decode + Encode + Clone,
     DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16>,
@@ -129,12 +164,17 @@ pub fn check_work_proof<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, Au
                 return Err(format!("extra data too long"));
             }
 
-            let mut work_header = header.clone();
-            let seal_owned : PowSeal<B, AuthorityId> = seal.to_owned();
-            let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal(seal_owned);
-            work_header.digest_mut().push(item);
+            // nonce MUST be mined for this shard
+            let (shard_num, _): (u16, u16) = header.digest().logs().iter().rev()
+                .filter_map(ShardingDigestItem::as_sharding_info)
+                .next()
+                .ok_or_else(|| format!("shard info not found"))?;
+            match proof_nonce.shard_num(NONCE_PREFIX) {
+                Some(n) if n == shard_num => {}
+                n => return Err(format!("Nonce proof: shard not match, need {}, got {:?}", shard_num, n)),
+            }
 
-            let hash = work_header.hash();
+            let hash = work_hash(header, seal, engine_id);
 
             let proof_pow_target = PowTarget::from(hash.as_ref());
 
@@ -142,7 +182,7 @@ pub fn check_work_proof<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, Au
                 return Err(format!("Nonce proof: pow target not satisified, need {}, got {}", seal.pow_target, proof_pow_target));
             }
 
-            let post_digest = work_header.digest_mut().pop().expect("must exist");
+            let post_digest = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal_with_id(engine_id, seal.to_owned());
 
             Ok((post_digest, hash))
         },
@@ -165,7 +205,7 @@ pub fn check_work_proof<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, Au
                 relay_proof: seal.relay_proof.clone(),
             };
             let mut header_with_pow_seal = header.clone();
-            let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal(pow_seal.clone());
+            let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal_with_id(engine_id, pow_seal.clone());
             header_with_pow_seal.digest_mut().push(item);
             let pre_hash = header_with_pow_seal.hash();
 
@@ -210,7 +250,7 @@ pub fn check_work_proof<B, AuthorityId>(header: &B::Header, seal: &PowSeal<B, Au
             //make hash
             let mut work_header = header.clone();
             let seal_owned : PowSeal<B, AuthorityId> = seal.to_owned();
-            let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal(seal_owned);
+            let item = <DigestItemFor<B> as CompatibleDigestItem<B, AuthorityId>>::pow_seal_with_id(engine_id, seal_owned);
             work_header.digest_mut().push(item);
 
             let hash = work_header.hash();
@@ -239,6 +279,7 @@ pub fn calc_pow_target<B, C, AuthorityId>(client: Arc<C>, header: &<B as Block>:
     let curr_block_id = BlockId::hash(*header.parent_hash());
     let genesis_pow_target = context.genesis_pow_target;
     let adj = context.genesis_pow_target_adj;
+    let engine_id = context.pow_engine_id;
     let curr_header = client.header(curr_block_id)
         .expect("parent block must exist for sealer; qed")
         .expect("parent block must exist for sealer; qed");
@@ -250,14 +291,14 @@ pub fn calc_pow_target<B, C, AuthorityId>(client: Arc<C>, header: &<B as Block>:
         return Ok(genesis_pow_target)
     } else if (next_num - one) % adj != Zero::zero() {
         let curr_pow_target = curr_header.digest().logs().iter().rev()
-            .filter_map(CompatibleDigestItem::as_pow_seal).next()
+            .filter_map(|item| item.as_pow_seal_with_id(engine_id)).next()
             .and_then(|seal| Some(seal.pow_target))
             .unwrap_or(genesis_pow_target);
         return Ok(curr_pow_target);
     }
 
     let curr_seal = curr_header.digest().logs().iter().rev()
-        .filter_map(CompatibleDigestItem::as_pow_seal).next()
+        .filter_map(|item| item.as_pow_seal_with_id(engine_id)).next()
         .expect("Seal must exist when adjustment comes; qed");
     let curr_pow_target = curr_seal.pow_target;
 
@@ -267,7 +308,7 @@ pub fn calc_pow_target<B, C, AuthorityId>(client: Arc<C>, header: &<B as Block>:
             .expect("parent block must exist for sealer; qed")
             .expect("parent block must exist for sealer; qed");
         let ancestor_seal = ancestor_header.digest().logs().iter().rev()
-            .filter_map(CompatibleDigestItem::as_pow_seal).next();
+            .filter_map(|item| item.as_pow_seal_with_id(engine_id)).next();
         match ancestor_seal {
             Some(seal) => {
                 (adj.as_(), seal.timestamp)
@@ -288,13 +329,85 @@ pub fn calc_pow_target<B, C, AuthorityId>(client: Arc<C>, header: &<B as Block>:
     Ok(new_pow_target)
 }
 
+/// Check that block body matches the extrinsics root committed in header
+pub fn check_extrinsics_root<B>(header: &B::Header, body: &[B::Extrinsic]) -> Result<(), String> where
+    B: Block,
+{
+    let root = <<B::Header as Header>::Hashing as HashT>::ordered_trie_root(
+        body.iter().map(Encode::encode).collect(),
+    );
+    if &root != header.extrinsics_root() {
+        return Err(format!("Extrinsics root not match, header: {:?}, body: {:?}", header.extrinsics_root(), root));
+    }
+    Ok(())
+}
+
+/// Reason relay proof of a block is rejected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelayRejectReason {
+    /// body does not match extrinsics root in header
+    ExtrinsicsRoot(String),
+    /// proof can not be generated from body
+    ProofUnavailable(String),
+    /// relay proof in seal is not the root generated from body
+    ProofMismatch { seal: H256, body: H256 },
+}
+
+impl RelayRejectReason {
+    /// short label of the reason, e.g. for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RelayRejectReason::ExtrinsicsRoot(_) => "extrinsics_root",
+            RelayRejectReason::ProofUnavailable(_) => "proof_unavailable",
+            RelayRejectReason::ProofMismatch { .. } => "proof_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for RelayRejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RelayRejectReason::ExtrinsicsRoot(e) => write!(f, "{}", e),
+            RelayRejectReason::ProofUnavailable(e) => write!(f, "{}", e),
+            RelayRejectReason::ProofMismatch { seal, body } => write!(f, "Relay proof not match, seal: {:?}, body: {:?}", seal, body),
+        }
+    }
+}
+
+/// Check that relay proof in seal is the root generated from block body,
+/// which in turn MUST match the extrinsics root in header
+pub fn check_relay_proof<B>(header: &B::Header, body: &[B::Extrinsic], relay_proof: H256, max_extrinsics: usize) -> Result<ExtrinsicProof, RelayRejectReason>
+    where
+        B: Block,
+        DigestItemFor<B>: yc_sharding::ShardingDigestItem<u16>,
+        <B as Block>::Hash: From<H256> + Ord,
+{
+    check_extrinsics_root::<B>(header, body).map_err(RelayRejectReason::ExtrinsicsRoot)?;
+    let (root, proof) = gen_extrinsic_proof::<B>(header, body, max_extrinsics)
+        .map_err(RelayRejectReason::ProofUnavailable)?;
+    if root != relay_proof {
+        return Err(RelayRejectReason::ProofMismatch { seal: relay_proof, body: root });
+    }
+    Ok(proof)
+}
+
+/// Default max extrinsics in a block body an extrinsic proof is generated for
+pub const DEFAULT_MAX_PROOF_EXTRINSICS: usize = 65536;
+
 /// Gen extrinsic proof for foreign chain.
-pub fn gen_extrinsic_proof<B>(header: &B::Header, body: &[B::Extrinsic]) -> (H256, ExtrinsicProof)
+///
+/// Bodies of more than `max_extrinsics` extrinsics are rejected,
+/// they make the proof slow to generate and huge.
+pub fn gen_extrinsic_proof<B>(header: &B::Header, body: &[B::Extrinsic], max_extrinsics: usize) -> Result<(H256, ExtrinsicProof), String>
     where
         B: Block,
         DigestItemFor<B>: yc_sharding::ShardingDigestItem<u16>,
         <B as Block>::Hash: From<H256> + Ord,
 {
+    if body.len() > max_extrinsics {
+        return Err(format!("Too many extrinsics for proof: {}, max: {}", body.len(), max_extrinsics));
+    }
+
     let shard_info = header.digest().logs().iter().rev()
         .filter_map(ShardingDigestItem::as_sharding_info)
         .next();
@@ -342,7 +455,7 @@ pub fn gen_extrinsic_proof<B>(header: &B::Header, body: &[B::Extrinsic]) -> (H25
     let layer2_root = layer2_tree.root();
     let multi_proof = MultiLayerProof::new_with_layer2(layer2_tree, layer1_merkles, );
     debug!("{} height:{}, proof: {:?}", Colour::White.bold().paint("Gen proof"), header.number(), &multi_proof);
-    (layer2_root, multi_proof.into_bytes())
+    Ok((layer2_root, multi_proof.into_flagged_bytes(true)))
 }
 
 #[derive(Clone, Debug)]
@@ -521,6 +634,85 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_nonce_shard_prefix() {
+        use yee_runtime::{DigestItem, Header as RuntimeHeader};
+        use yp_consensus_pow::YEE_POW_ENGINE_ID;
+
+        let proof = ProofNonce::get_with_shard(NONCE_PREFIX, 0x0102, 12, 7);
+        assert_eq!(&proof.extra_data[..10], b"yeeroot-\x02\x01");
+        assert_eq!(proof.extra_data.len(), 20);
+        assert_eq!(proof.shard_num(NONCE_PREFIX), Some(0x0102));
+        assert_eq!(ProofNonce::get_with_prefix_len("other---", 12, 7).shard_num(NONCE_PREFIX), None);
+
+        let mut header = RuntimeHeader::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+        header.digest_mut().push(<DigestItem as ShardingDigestItem<u16>>::sharding_info(1, 4));
+        let seal = |proof: ProofNonce| PowSeal::<Block, u64> {
+            authority_id: 0,
+            pow_target: PowTarget::max_value(),
+            timestamp: 0,
+            work_proof: WorkProof::Nonce(proof),
+            relay_proof: Default::default(),
+        };
+
+        let ok = seal(ProofNonce::get_with_shard(NONCE_PREFIX, 1, 12, 7));
+        assert!(check_work_proof(&header, &ok, YEE_POW_ENGINE_ID).is_ok());
+
+        let wrong_shard = seal(ProofNonce::get_with_shard(NONCE_PREFIX, 2, 12, 7));
+        assert!(check_work_proof(&header, &wrong_shard, YEE_POW_ENGINE_ID).is_err());
+
+        let no_shard = seal(ProofNonce::get_with_prefix_len("other---", 12, 7));
+        assert!(check_work_proof(&header, &no_shard, YEE_POW_ENGINE_ID).is_err());
+    }
+
+    #[test]
+    fn test_check_extrinsics_root() {
+        use yee_runtime::{Header as RuntimeHeader, TimestampCall};
+
+        let body = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(42)))];
+        let root = BlakeTwo256::ordered_trie_root(body.iter().map(Encode::encode).collect());
+        let header = RuntimeHeader::new(1, root, Default::default(), Default::default(), Default::default());
+
+        assert_eq!(check_extrinsics_root::<Block>(&header, &body), Ok(()));
+
+        let mismatched = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(43)))];
+        assert!(check_extrinsics_root::<Block>(&header, &mismatched).is_err());
+    }
+
+    #[test]
+    fn test_check_relay_proof() {
+        use yee_runtime::{DigestItem, Header as RuntimeHeader, TimestampCall};
+
+        let body = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(42)))];
+        let root = BlakeTwo256::ordered_trie_root(body.iter().map(Encode::encode).collect());
+        let mut header = RuntimeHeader::new(1, root, Default::default(), Default::default(), Default::default());
+        header.digest_mut().push(<DigestItem as ShardingDigestItem<u16>>::sharding_info(0, 4));
+
+        let (relay_proof, _) = gen_extrinsic_proof::<Block>(&header, &body, DEFAULT_MAX_PROOF_EXTRINSICS).unwrap();
+        assert!(check_relay_proof::<Block>(&header, &body, relay_proof, DEFAULT_MAX_PROOF_EXTRINSICS).is_ok());
+
+        let tampered: H256 = [1u8; 32].into();
+        assert!(check_relay_proof::<Block>(&header, &body, tampered, DEFAULT_MAX_PROOF_EXTRINSICS).is_err());
+
+        let mismatched = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(43)))];
+        assert!(check_relay_proof::<Block>(&header, &mismatched, relay_proof, DEFAULT_MAX_PROOF_EXTRINSICS).is_err());
+    }
+
+    #[test]
+    fn test_gen_extrinsic_proof_max_extrinsics() {
+        use yee_runtime::{DigestItem, Header as RuntimeHeader, TimestampCall};
+
+        let body: Vec<UncheckedExtrinsic> = (0..4)
+            .map(|i| UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(i))))
+            .collect();
+        let mut header = RuntimeHeader::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+        header.digest_mut().push(<DigestItem as ShardingDigestItem<u16>>::sharding_info(0, 4));
+
+        assert!(gen_extrinsic_proof::<Block>(&header, &body, 5).is_ok());
+        assert!(gen_extrinsic_proof::<Block>(&header, &body, 4).is_ok());
+        assert!(gen_extrinsic_proof::<Block>(&header, &body, 3).is_err());
+    }
+
     #[test]
     fn test_merkle_root() {
         let shard0_header_hash: H256 = [1u8; 32].into();
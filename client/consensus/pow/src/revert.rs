@@ -0,0 +1,210 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Revert shard config after a failed scale out
+
+use std::sync::Arc;
+
+use futures::{future, Future, Stream, StreamExt};
+use log::warn;
+use parking_lot::Mutex;
+use sp_runtime::traits::{AtLeast32Bit, Block, DigestItemFor, Header, NumberFor};
+use yc_sharding::{ScaleOutPhase, ScaleOutPhaseDigestItem, ShardingDigestItem};
+
+use crate::TriggerExit;
+
+/// Shard config the node runs with, kept by the service across restart
+pub trait ShardConfigStore: Send + Sync {
+	fn set_shard_config(&self, shard_num: u16, shard_count: u16);
+}
+
+/// Watches scale out phases of headers imported as new best.
+///
+/// Fed after import rather than from the verifier,
+/// so headers failing later checks or on side forks do not move the window.
+///
+/// If `Committed` does not follow `Commiting` within `window` blocks,
+/// shard config is reverted to the shard info before scale out and the service restarted.
+pub struct ScaleOutRevertWatch<N> {
+	window: N,
+	store: Arc<dyn ShardConfigStore>,
+	trigger_exit: Arc<dyn TriggerExit>,
+	/// block number and shard info (num, count) of the header `Commiting` first seen
+	committing: Mutex<Option<(N, (u16, u16))>>,
+}
+
+impl<N: AtLeast32Bit + Copy> ScaleOutRevertWatch<N> {
+	pub fn new(window: N, store: Arc<dyn ShardConfigStore>, trigger_exit: Arc<dyn TriggerExit>) -> Self {
+		ScaleOutRevertWatch {
+			window,
+			store,
+			trigger_exit,
+			committing: Mutex::new(None),
+		}
+	}
+
+	/// feed an imported header, returns true if shard config reverted
+	pub fn on_header(&self, number: N, shard_info: (u16, u16), phase: Option<&ScaleOutPhase<N, u16>>) -> bool {
+		let mut committing = self.committing.lock();
+		match phase {
+			Some(ScaleOutPhase::Committing { .. }) => {
+				if committing.is_none() {
+					*committing = Some((number, shard_info));
+				}
+			}
+			Some(ScaleOutPhase::Committed { .. }) => {
+				*committing = None;
+			}
+			_ => {}
+		}
+
+		match *committing {
+			Some((since, (shard_num, shard_count))) if number >= since + self.window => {
+				warn!("Scale out not committed since {}, revert to shard {} of {}", since, shard_num, shard_count);
+				self.store.set_shard_config(shard_num, shard_count);
+				self.trigger_exit.trigger_restart();
+				*committing = None;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// feed a header imported as new best, returns true if shard config reverted
+	pub fn on_best_header<B>(&self, header: &B::Header) -> bool where
+		B: Block,
+		B::Header: Header<Number=N>,
+		DigestItemFor<B>: ShardingDigestItem<u16> + ScaleOutPhaseDigestItem<N, u16>,
+	{
+		let logs = header.digest().logs();
+		let shard_info = logs.iter().rev().filter_map(ShardingDigestItem::as_sharding_info).next();
+		let phase = logs.iter().rev().filter_map(ScaleOutPhaseDigestItem::as_scale_out_phase).next();
+		match shard_info {
+			Some(shard_info) => self.on_header(*header.number(), shard_info, phase.as_ref()),
+			None => false,
+		}
+	}
+}
+
+/// Feed headers imported as new best to `watch`,
+/// e.g. `client.import_notification_stream().filter(|n| n.is_new_best).map(|n| n.header)`.
+pub fn watch_best_headers<B, S>(
+	watch: Arc<ScaleOutRevertWatch<NumberFor<B>>>,
+	best_headers: S,
+) -> impl Future<Output=()> where
+	B: Block,
+	S: Stream<Item=B::Header>,
+	DigestItemFor<B>: ShardingDigestItem<u16> + ScaleOutPhaseDigestItem<NumberFor<B>, u16>,
+{
+	best_headers.for_each(move |header| {
+		watch.on_best_header::<B>(&header);
+		future::ready(())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use super::*;
+
+	#[derive(Default)]
+	struct RecordingStore(Mutex<Vec<(u16, u16)>>);
+
+	impl ShardConfigStore for RecordingStore {
+		fn set_shard_config(&self, shard_num: u16, shard_count: u16) {
+			self.0.lock().push((shard_num, shard_count));
+		}
+	}
+
+	#[derive(Default)]
+	struct CountingRestart(AtomicUsize);
+
+	impl TriggerExit for CountingRestart {
+		fn trigger_restart(&self) {
+			self.0.fetch_add(1, Ordering::SeqCst);
+		}
+
+		fn trigger_stop(&self) {}
+	}
+
+	#[test]
+	fn test_stalled_scale_out_reverted() {
+		let store = Arc::new(RecordingStore::default());
+		let restart = Arc::new(CountingRestart::default());
+		let watch = ScaleOutRevertWatch::<u64>::new(3, store.clone(), restart.clone());
+
+		let committing = ScaleOutPhase::Committing { shard_count: 8 };
+		assert!(!watch.on_header(10, (1, 4), Some(&committing)));
+		assert!(!watch.on_header(11, (1, 4), None));
+		assert!(!watch.on_header(12, (1, 4), None));
+		assert!(watch.on_header(13, (1, 4), None));
+
+		assert_eq!(*store.0.lock(), vec![(1, 4)]);
+		assert_eq!(restart.0.load(Ordering::SeqCst), 1);
+
+		// reverted once only
+		assert!(!watch.on_header(14, (1, 4), None));
+		assert_eq!(restart.0.load(Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn test_committed_scale_out_kept() {
+		let store = Arc::new(RecordingStore::default());
+		let restart = Arc::new(CountingRestart::default());
+		let watch = ScaleOutRevertWatch::<u64>::new(3, store.clone(), restart.clone());
+
+		let committing = ScaleOutPhase::Committing { shard_count: 8 };
+		let committed = ScaleOutPhase::Committed { shard_num: 5, shard_count: 8 };
+		assert!(!watch.on_header(10, (1, 4), Some(&committing)));
+		assert!(!watch.on_header(11, (1, 4), Some(&committed)));
+		assert!(!watch.on_header(20, (5, 8), None));
+
+		assert!(store.0.lock().is_empty());
+		assert_eq!(restart.0.load(Ordering::SeqCst), 0);
+	}
+
+	#[test]
+	fn test_watch_best_headers() {
+		use sp_core::H256;
+		use sp_runtime::generic::DigestItem;
+		use yee_runtime::{Block as RuntimeBlock, Header as RuntimeHeader};
+
+		let store = Arc::new(RecordingStore::default());
+		let restart = Arc::new(CountingRestart::default());
+		let watch = Arc::new(ScaleOutRevertWatch::<u32>::new(3, store.clone(), restart.clone()));
+
+		let header = |number: u32, phase: Option<ScaleOutPhase<u32, u16>>| {
+			let mut header = RuntimeHeader::new(number, Default::default(), Default::default(), Default::default(), Default::default());
+			header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(1, 4));
+			if let Some(phase) = phase {
+				header.digest_mut().push(<DigestItem<H256> as ScaleOutPhaseDigestItem<u32, u16>>::scale_out_phase(phase));
+			}
+			header
+		};
+		let best_headers = vec![
+			header(10, Some(ScaleOutPhase::Committing { shard_count: 8 })),
+			header(11, None),
+			header(12, None),
+			header(13, None),
+		];
+
+		futures::executor::block_on(watch_best_headers::<RuntimeBlock, _>(watch, futures::stream::iter(best_headers)));
+		assert_eq!(*store.0.lock(), vec![(1, 4)]);
+		assert_eq!(restart.0.load(Ordering::SeqCst), 1);
+	}
+}
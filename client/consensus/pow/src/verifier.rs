@@ -20,10 +20,11 @@
 use ansi_term::Colour;
 use log::{error, warn};
 use merkle_light::proof::Proof as MLProof;
+use parking_lot::Mutex;
 use sp_core::H256;
 
 use {
-	std::{marker::PhantomData, sync::Arc},
+	std::{collections::HashMap, fmt, marker::PhantomData, sync::Arc},
 };
 use {
 	sp_api::ProvideRuntimeApi,
@@ -42,15 +43,18 @@ use {
 		generic,
 		Justification,
 		Proof,
+		generic::BlockId,
 		traits::{
 			BlakeTwo256,
 			Block as BlockT, DigestItemFor,
 			Header,
-			NumberFor,
+			NumberFor, One,
 		},
+		ConsensusEngineId,
 	},
 	yc_util::relay_decode::RelayTransfer,
-	yp_consensus_pow::YeePOWApi,
+	sp_block_builder::BlockBuilder as BlockBuilderApi,
+	yp_consensus_pow::{PowInfo, RewardCondition, SlashReason, YeePOWApi, inherents::PowInherentData},
 	// foreign_chain::{ForeignChain, ForeignChainConfig},
 	yp_sharding::ShardingAPI,
 };
@@ -59,11 +63,48 @@ use yc_util_merkle::{MultiLayerProof, ProofAlgorithm, ProofHash};
 use yp_context::Context;
 use yp_sharding::utils::shard_num_for;
 
-use crate::pow::{calc_pow_target, check_work_proof, gen_extrinsic_proof, PowSeal};
+use crate::pow::{calc_pow_target, check_relay_proof, check_work_proof, gen_extrinsic_proof, PowSeal, RelayRejectReason};
 use crate::ShardExtra;
 
 use super::CompatibleDigestItem;
 
+/// Default max count of digest items in a header
+pub const DEFAULT_MAX_DIGEST_ITEMS: usize = 16;
+
+/// Default max total encoded size in bytes of digest items in a header
+pub const DEFAULT_MAX_DIGEST_SIZE: usize = 4096;
+
+/// Bound of header digest, headers beyond it are rejected
+#[derive(Clone, Debug)]
+pub struct DigestLimit {
+	pub max_items: usize,
+	pub max_size: usize,
+}
+
+impl Default for DigestLimit {
+	fn default() -> Self {
+		DigestLimit {
+			max_items: DEFAULT_MAX_DIGEST_ITEMS,
+			max_size: DEFAULT_MAX_DIGEST_SIZE,
+		}
+	}
+}
+
+impl DigestLimit {
+	/// check count and total size of header digest items
+	pub fn check<H: Header>(&self, header: &H) -> Result<(), String> {
+		let logs = header.digest().logs();
+		if logs.len() > self.max_items {
+			return Err(format!("Too many digest items, max {}, got {}", self.max_items, logs.len()));
+		}
+		let size: usize = logs.iter().map(|item| item.encoded_size()).sum();
+		if size > self.max_size {
+			return Err(format!("Digest too large, max {}, got {}", self.max_size, size));
+		}
+		Ok(())
+	}
+}
+
 /// Verifier for POW blocks.
 pub struct PowVerifier<B, C, AccountId, AuthorityId> where
 	B: BlockT
@@ -74,6 +115,8 @@ pub struct PowVerifier<B, C, AccountId, AuthorityId> where
 	pub phantom: PhantomData<AuthorityId>,
 	pub shard_extra: ShardExtra<AccountId>,
 	pub context: Context<B>,
+	pub digest_limit: DigestLimit,
+	pub import_errors: Arc<ImportErrorCounts>,
 }
 
 #[forbid(deprecated)]
@@ -84,8 +127,9 @@ impl<B, C, AccountId, AuthorityId> Verifier<B> for PowVerifier<B, C, AccountId,
 	AccountId: Decode + Encode + Clone + Send + Sync + Default,
 	AuthorityId: Decode + Encode + Clone + Send + Sync,
 	C: HeaderBackend<B> + ProvideRuntimeApi<B>,
-	C::Api: ShardingAPI<B> + YeePOWApi<B>,
+	C::Api: ShardingAPI<B> + YeePOWApi<B> + BlockBuilderApi<B>,
 	H256: From<B::Hash>,
+	B::Hash: From<H256> + Ord,
 {
 	fn verify(
 		&mut self,
@@ -98,17 +142,37 @@ impl<B, C, AccountId, AuthorityId> Verifier<B> for PowVerifier<B, C, AccountId,
 		let number = header.number().clone();
 		let hash = header.hash();
 
+		// check header digest bound before anything else
+		self.digest_limit.check(&header).map_err(|e| {
+			error!("{}: {}", Colour::Red.paint("check digest failed"), e);
+			e
+		})?;
+
 		// check if header has a valid work proof
 		let (pre_header, seal) = self.check_header(header, hash.clone())
 			.map_err(|e| {
 				error!("{}: {}", Colour::Red.paint("check header failed"), e);
 				e
 			})?;
-		let proof_root = seal.as_pow_seal().ok_or_else(|| {
+		let proof_root = seal.as_pow_seal_with_id(self.context.pow_engine_id).ok_or_else(|| {
 			let e = format!("Header {:?} not sealed", hash);
 			error!("{}: {}", Colour::Red.paint("get proof root failed"), e);
 			e
 		})?.relay_proof;
+		// check relay proof against body, a mismatching proof slashes the block,
+		// which only imports when its own pow inherent declares the slash
+		if let Some(exs) = body.as_ref() {
+			let relay = check_block_relay::<B>(&pre_header, exs, proof_root, self.context.max_proof_extrinsics);
+			let reward_condition = relay_reward_condition(relay).map_err(|e| {
+				error!("{}, hash:{}: {:?}", Colour::Red.paint("Relay proof validate failed"), hash, e);
+				self.import_errors.record(&e);
+				e.to_string()
+			})?;
+			if let RewardCondition::Slash(reason) = &reward_condition {
+				warn!("{}, hash:{}: {:?}", Colour::Yellow.paint("Relay proof mismatch, block slashed"), hash, reason);
+				self.check_slashed(&pre_header, exs, reward_condition.clone())?;
+			}
+		}
 		// check proof.
 		// TODO
 		// self.check_relay_merkle_proof(proof.clone(), proof_root)
@@ -138,13 +202,46 @@ impl<B, C, AccountId, AuthorityId> Verifier<B> for PowVerifier<B, C, AccountId,
 	}
 }
 
+impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> where
+	B: BlockT,
+	AccountId: Decode + Encode + Clone,
+	C: ProvideRuntimeApi<B>,
+	C::Api: BlockBuilderApi<B>,
+{
+	/// Check the slashed block declares `reward_condition` in its pow inherent.
+	///
+	/// Self declaration is the only way a slashed block imports, a block not declaring
+	/// its slash is rejected. The runtime enforces it in `check_inherent` of the pow pallet,
+	/// the block reward is then withheld on import.
+	fn check_slashed(&self, header: &B::Header, body: &[B::Extrinsic], reward_condition: RewardCondition) -> Result<(), String> {
+		let mut inherent_data = self.inherent_data_providers.create_inherent_data()
+			.map_err(|e| format!("{:?}", e))?;
+		let pow_info: PowInfo<AccountId> = inherent_data.pow_inherent_data()
+			.map_err(|e| format!("{:?}", e))?;
+		inherent_data.pow_replace_inherent_data(PowInfo { reward_condition, ..pow_info });
+
+		let block = B::new(header.clone(), body.to_vec());
+		let result = self.client.runtime_api()
+			.check_inherents(&BlockId::Hash(*header.parent_hash()), block, inherent_data)
+			.map_err(|e| format!("{:?}", e))?;
+		if !result.ok() {
+			let errors = result.into_errors()
+				.map(|(id, e)| self.inherent_data_providers.error_to_string(&id, &e))
+				.collect::<Vec<_>>();
+			return Err(format!("Slashed block {:?} not declared: {}", header.hash(), errors.join(", ")));
+		}
+		Ok(())
+	}
+}
+
 impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> where
 	B: BlockT,
 	DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16> + ScaleOutPhaseDigestItem<NumberFor<B>, u16>,
 	C: Send + Sync,
 	AccountId: Decode + Encode + Clone + Send + Sync + Default,
 	AuthorityId: Decode + Encode + Clone + Send + Sync,
-	C: HeaderBackend<B>,
+	C: HeaderBackend<B> + ProvideRuntimeApi<B>,
+	C::Api: ShardingAPI<B>,
 	H256: From<B::Hash>,
 {
 	/// check body
@@ -152,7 +249,7 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 		match body.as_ref() {
 			Some(exs) => {
 				// check proof root
-				let (root, proof) = gen_extrinsic_proof::<B>(&pre_header, &exs);
+				let (root, proof) = gen_extrinsic_proof::<B>(&pre_header, &exs, self.context.max_proof_extrinsics)?;
 				if root != proof_root {
 					return Err("Proof is invalid.".to_string());
 				}
@@ -169,7 +266,7 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 	fn check_relay_merkle_proof(&self, proof: Option<Proof>, p_h: H256) -> Result<(), String> {
 		if let Some(proof) = proof.as_ref() {
 			let mut validate_proof = false;
-			if let Ok(mlp) = MultiLayerProof::from_bytes(proof) {
+			if let Ok(mlp) = MultiLayerProof::from_flagged_bytes(proof) {
 				// check proof root.
 				let checked = match mlp.layer2_root() {
 					Some(root) => root == p_h,
@@ -199,32 +296,17 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 		let shard_info: Option<(u16, u16)> = logs.iter().rev()
 			.filter_map(ShardingDigestItem::as_sharding_info)
 			.next();
-		let (tc, _cs) = match shard_info {
-			Some(info) => info,
-			None => { return Err("Can't get shard info in header".to_string()); }
-		};
+		if shard_info.is_none() {
+			return Err("Can't get shard info in header".to_string());
+		}
 		for tx in exs {
 			match RelayTransfer::decode(tx.encode().as_slice()) {
-				Some(rt) => {
+				Ok(rt) => {
 					let rt: RelayTransfer<AccountId, u128, B::Hash> = rt;
-					let h = rt.hash();
-					let id = generic::BlockId::hash(h);
-					let ds = yp_sharding::utils::shard_num_for(&rt.transfer.sender(), tc as u16)
-						.expect("Internal error. Get shard num failed.");
-					if let Some(lc) = self.foreign_chains.read().as_ref().unwrap().get_shard_component(ds) {
-						match lc.client().proof(&id).map_err(|_| err_str)? {
-							Some(proof) => {
-								let proof = MultiLayerProof::from_bytes(proof.as_slice()).map_err(|_| err_str)?;
-								if proof.contains(ds, h) {
-									continue;
-								}
-							}
-							None => { return Err(err_str.to_string()); }
-						}
-					}
-					panic!("Internal error. Can't get shard component");
+					rt.sender().account_id().ok_or(err_str)?;
+					// TODO: check the origin shard proof once foreign chains are wired into the verifier
 				}
-				None => continue,
+				Err(_) => continue,
 			}
 		}
 		Ok(())
@@ -237,7 +319,7 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 			Some(x) => x,
 			None => return Err(" get digest item failed.".to_string()),
 		};
-		let seal = digest_item.as_pow_seal().ok_or_else(|| {
+		let seal = digest_item.as_pow_seal_with_id(self.context.pow_engine_id).ok_or_else(|| {
 			format!("Header {:?} not sealed", hash)
 		})?;
 
@@ -247,7 +329,7 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 
 		self.check_other_logs(&header)?;
 
-		check_work_proof(&header, &seal)?;
+		check_work_proof(&header, &seal, self.context.pow_engine_id)?;
 
 		Ok((header, digest_item))
 	}
@@ -271,14 +353,19 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 			.expect("parent header must exist.")
 			.expect("parent header must exist.");
 		let number = *header.number();
-		let observe_blocks = self.context.genesis_scale_out_observe_blocks;
+		// observe window the runtime opened this block's phase with
+		let observe_blocks = self.client.runtime_api()
+			.get_scale_out_observe_blocks(&BlockId::Hash(*header.parent_hash()))
+			.map_err(|e| format!("Get scale out observe blocks failed: {:?}", e))?;
 		let ok = match parent.digest().logs().iter().rev().filter_map(ScaleOutPhaseDigestItem::as_scale_out_phase).next() {
 			Some(ScaleOutPhase::Started { observe_util: p_observe_util, shard_num: _p_shard_num }) => {
 				match header.digest().logs().iter().rev().filter_map(ScaleOutPhaseDigestItem::as_scale_out_phase).next() {
 					Some(ScaleOutPhase::Started { observe_util, shard_num }) => {
-						let ok = p_observe_util == observe_util
-							&& (shard_num == digest_shard_num || shard_num == digest_shard_num + digest_shard_count)
-							&& number < observe_util;
+						// same window, or another window when the scale out was not ready at its end
+						let window_ok = (p_observe_util == observe_util && number < observe_util)
+							|| (p_observe_util == number && number + observe_blocks == observe_util);
+						let ok = window_ok
+							&& (shard_num == digest_shard_num || shard_num == digest_shard_num + digest_shard_count);
 						if !ok {
 							error!("parent status: {}, current status: {}", Colour::Red.paint("Started"), Colour::Red.paint("Started"));
 						}
@@ -431,6 +518,120 @@ impl<B, C, AccountId, AuthorityId> PowVerifier<B, C, AccountId, AuthorityId> whe
 	}
 }
 
+/// Reason a block is rejected by the verifier, beyond POW failures
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportError<N> {
+	/// relay proof in seal does not match block body
+	InvalidRelay { block: N, reason: RelayRejectReason },
+}
+
+impl<N> ImportError<N> {
+	/// short label of the error, e.g. for metrics
+	pub fn kind(&self) -> &'static str {
+		match self {
+			ImportError::InvalidRelay { reason, .. } => reason.kind(),
+		}
+	}
+}
+
+impl<N: fmt::Display> fmt::Display for ImportError<N> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ImportError::InvalidRelay { block, reason } => write!(f, "Invalid relay in block {}: {}", block, reason),
+		}
+	}
+}
+
+/// Count of blocks rejected by the verifier per `ImportError::kind`
+#[derive(Default)]
+pub struct ImportErrorCounts(Mutex<HashMap<&'static str, u64>>);
+
+impl ImportErrorCounts {
+	pub fn record<N>(&self, e: &ImportError<N>) {
+		*self.0.lock().entry(e.kind()).or_insert(0) += 1;
+	}
+
+	pub fn get(&self, kind: &str) -> u64 {
+		self.0.lock().get(kind).cloned().unwrap_or(0)
+	}
+}
+
+/// Check relay proof of a block against its body
+pub fn check_block_relay<B>(
+	header: &B::Header,
+	body: &[B::Extrinsic],
+	relay_proof: H256,
+	max_extrinsics: usize,
+) -> Result<(), ImportError<NumberFor<B>>> where
+	B: BlockT,
+	DigestItemFor<B>: ShardingDigestItem<u16>,
+	B::Hash: From<H256> + Ord,
+{
+	check_relay_proof::<B>(header, body, relay_proof, max_extrinsics)
+		.map(|_| ())
+		.map_err(|reason| ImportError::InvalidRelay { block: *header.number(), reason })
+}
+
+/// Reward condition of a block from its relay check.
+///
+/// A relay proof not matching an otherwise consistent body slashes the block instead of rejecting it,
+/// other failures still reject it.
+pub fn relay_reward_condition<N>(relay: Result<(), ImportError<N>>) -> Result<RewardCondition, ImportError<N>> {
+	match relay {
+		Ok(()) => Ok(RewardCondition::Normal),
+		Err(ImportError::InvalidRelay { reason: RelayRejectReason::ProofMismatch { .. }, .. }) =>
+			Ok(RewardCondition::Slash(SlashReason::InvalidRelayProof)),
+		Err(e) => Err(e),
+	}
+}
+
+/// Reason a header fails offline verification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+	/// last digest item is not a POW seal
+	NotSealed,
+	/// seal does not carry a valid work proof
+	InvalidWorkProof(String),
+	/// parent hash is not the hash of previous header
+	ParentMismatch,
+	/// number is not previous header number plus one
+	NumberMismatch,
+}
+
+/// Verify POW seals and parent linkage of a header chain segment without a client.
+///
+/// Pow target of each seal is taken as is, since recalculating it needs the chain state.
+/// Seals are read and checked under `engine_id`, i.e. `Context::pow_engine_id` of the chain.
+/// Returns index and reason of the first failed header.
+pub fn verify_header_chain<B, AuthorityId>(
+	headers: &[B::Header],
+	engine_id: ConsensusEngineId,
+) -> Result<(), (usize, VerifyError)> where
+	B: BlockT,
+	AuthorityId: Decode + Encode + Clone,
+	DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16>,
+{
+	for (i, header) in headers.iter().enumerate() {
+		if i > 0 {
+			let parent = &headers[i - 1];
+			if header.parent_hash() != &parent.hash() {
+				return Err((i, VerifyError::ParentMismatch));
+			}
+			if *header.number() != *parent.number() + One::one() {
+				return Err((i, VerifyError::NumberMismatch));
+			}
+		}
+
+		let mut pre_header = header.clone();
+		let seal = pre_header.digest_mut().pop()
+			.and_then(|item| item.as_pow_seal_with_id(engine_id))
+			.ok_or((i, VerifyError::NotSealed))?;
+		check_work_proof(&pre_header, &seal, engine_id)
+			.map_err(|e| (i, VerifyError::InvalidWorkProof(e)))?;
+	}
+	Ok(())
+}
+
 /// check scale
 pub fn check_scale<B, AccountId>(
 	header: &B::Header,
@@ -490,7 +691,358 @@ fn get_original_shard_num(shard_num: u16, shard_count: u16, original_shard_count
 
 #[cfg(test)]
 mod tests {
-	use crate::verifier::get_original_shard_num;
+	use sp_runtime::generic::DigestItem;
+	use yee_runtime::Header as RuntimeHeader;
+	use yp_consensus_pow::YEE_POW_ENGINE_ID;
+
+	use crate::{NONCE_PREFIX, ProofNonce, WorkProof};
+	use crate::verifier::{DigestLimit, get_original_shard_num};
+
+	use super::*;
+
+	#[test]
+	fn test_digest_limit() {
+		let mut header = RuntimeHeader::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+		header.digest_mut().push(DigestItem::Other(vec![0u8; 8]));
+		header.digest_mut().push(DigestItem::Seal(*b"Yee!", vec![0u8; 64]));
+		assert_eq!(DigestLimit::default().check(&header), Ok(()));
+
+		let limit = DigestLimit { max_items: 4, max_size: 128 };
+		let mut stuffed = header.clone();
+		for _ in 0..3 {
+			stuffed.digest_mut().push(DigestItem::Other(vec![0u8; 8]));
+		}
+		assert!(limit.check(&stuffed).is_err());
+
+		let mut oversized = header.clone();
+		oversized.digest_mut().push(DigestItem::Other(vec![0u8; 64]));
+		assert!(limit.check(&oversized).is_err());
+		assert_eq!(limit.check(&header), Ok(()));
+	}
+
+	fn sealed_header(number: u32, parent_hash: H256, work_proof: WorkProof<yee_runtime::Block>) -> RuntimeHeader {
+		sealed_header_with_id(YEE_POW_ENGINE_ID, number, parent_hash, work_proof)
+	}
+
+	fn sealed_header_with_id(
+		engine_id: ConsensusEngineId,
+		number: u32,
+		parent_hash: H256,
+		work_proof: WorkProof<yee_runtime::Block>,
+	) -> RuntimeHeader {
+		let mut header = RuntimeHeader::new(number, Default::default(), Default::default(), parent_hash, Default::default());
+		header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(0, 1));
+		let seal = PowSeal::<yee_runtime::Block, u64> {
+			authority_id: 0,
+			pow_target: yp_consensus_pow::PowTarget::max_value(),
+			timestamp: number as u64,
+			work_proof,
+			relay_proof: Default::default(),
+		};
+		header.digest_mut().push(<DigestItem<H256> as CompatibleDigestItem<yee_runtime::Block, u64>>::pow_seal_with_id(engine_id, seal));
+		header
+	}
+
+	fn nonce_proof(nonce: u64) -> WorkProof<yee_runtime::Block> {
+		WorkProof::Nonce(ProofNonce::get_with_shard(NONCE_PREFIX, 0, 12, nonce))
+	}
+
+	#[test]
+	fn test_verify_header_chain() {
+		let mut headers = vec![sealed_header(1, Default::default(), nonce_proof(1))];
+		for number in 2..5 {
+			let parent_hash = headers.last().unwrap().hash();
+			headers.push(sealed_header(number, parent_hash, nonce_proof(number as u64)));
+		}
+		assert_eq!(verify_header_chain::<yee_runtime::Block, u64>(&headers, YEE_POW_ENGINE_ID), Ok(()));
+
+		// bad seal in the middle, children relinked to it
+		let mut bad = headers.clone();
+		bad[2] = sealed_header(3, bad[1].hash(), WorkProof::Unknown);
+		bad[3] = sealed_header(4, bad[2].hash(), nonce_proof(4));
+		match verify_header_chain::<yee_runtime::Block, u64>(&bad, YEE_POW_ENGINE_ID) {
+			Err((2, VerifyError::InvalidWorkProof(_))) => {}
+			other => panic!("unexpected result: {:?}", other),
+		}
+
+		// broken linkage
+		let mut unlinked = headers.clone();
+		unlinked[3] = sealed_header(4, Default::default(), nonce_proof(4));
+		assert_eq!(verify_header_chain::<yee_runtime::Block, u64>(&unlinked, YEE_POW_ENGINE_ID), Err((3, VerifyError::ParentMismatch)));
+	}
+
+	#[test]
+	fn test_verify_header_chain_engine_id() {
+		let custom_id = *b"YeeT";
+		let mut headers = vec![sealed_header_with_id(custom_id, 1, Default::default(), nonce_proof(1))];
+		for number in 2..4 {
+			let parent_hash = headers.last().unwrap().hash();
+			headers.push(sealed_header_with_id(custom_id, number, parent_hash, nonce_proof(number as u64)));
+		}
+		assert_eq!(verify_header_chain::<yee_runtime::Block, u64>(&headers, custom_id), Ok(()));
+		assert_eq!(verify_header_chain::<yee_runtime::Block, u64>(&headers, YEE_POW_ENGINE_ID), Err((0, VerifyError::NotSealed)));
+	}
+
+	#[test]
+	fn test_check_block_relay() {
+		use sp_runtime::traits::Hash;
+		use yee_runtime::{Call, TimestampCall, UncheckedExtrinsic};
+
+		let body = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(42)))];
+		let root = BlakeTwo256::ordered_trie_root(body.iter().map(Encode::encode).collect());
+		let mut header = RuntimeHeader::new(7, root, Default::default(), Default::default(), Default::default());
+		header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(0, 4));
+
+		let (relay_proof, _) = gen_extrinsic_proof::<yee_runtime::Block>(&header, &body, 16).unwrap();
+		assert_eq!(check_block_relay::<yee_runtime::Block>(&header, &body, relay_proof, 16), Ok(()));
+
+		let bad: H256 = [1u8; 32].into();
+		assert_eq!(
+			check_block_relay::<yee_runtime::Block>(&header, &body, bad, 16),
+			Err(ImportError::InvalidRelay { block: 7, reason: RelayRejectReason::ProofMismatch { seal: bad, body: relay_proof } })
+		);
+	}
+
+	/// client serving a single parent header, runtime apis mocked
+	struct TestClient {
+		parent: RuntimeHeader,
+		slash_declared: bool,
+	}
+
+	impl HeaderBackend<yee_runtime::Block> for TestClient {
+		fn header(&self, _id: BlockId<yee_runtime::Block>) -> sp_blockchain::Result<Option<RuntimeHeader>> {
+			Ok(Some(self.parent.clone()))
+		}
+
+		fn info(&self) -> sp_blockchain::Info<yee_runtime::Block> {
+			unimplemented!()
+		}
+
+		fn status(&self, _id: BlockId<yee_runtime::Block>) -> sp_blockchain::Result<sp_blockchain::BlockStatus> {
+			Ok(sp_blockchain::BlockStatus::InChain)
+		}
+
+		fn number(&self, _hash: H256) -> sp_blockchain::Result<Option<u32>> {
+			Ok(Some(*self.parent.number()))
+		}
+
+		fn hash(&self, _number: u32) -> sp_blockchain::Result<Option<H256>> {
+			Ok(Some(self.parent.hash()))
+		}
+	}
+
+	/// `slash_declared` stands for the pow inherent of the checked block declaring a slash
+	struct TestApi {
+		slash_declared: bool,
+	}
+
+	sp_api::mock_impl_runtime_apis! {
+		impl ShardingAPI<yee_runtime::Block> for TestApi {
+			fn get_genesis_shard_count() -> u16 { 1 }
+			fn get_curr_shard() -> Option<u16> { Some(0) }
+			fn get_shard_count() -> u16 { 1 }
+			fn get_scale_out_observe_blocks() -> u32 { 10 }
+			fn scale_out_progress() -> Option<u8> { None }
+			fn get_current_scale_out_phase() -> Option<ScaleOutPhase<u32, u16>> { None }
+		}
+
+		impl YeePOWApi<yee_runtime::Block> for TestApi {
+			fn genesis_pow_target() -> yp_consensus_pow::PowTarget { yp_consensus_pow::PowTarget::max_value() }
+			fn pow_target_adj() -> u32 { 10 }
+			fn target_block_time() -> u64 { 30 }
+		}
+
+		impl sp_block_builder::BlockBuilder<yee_runtime::Block> for TestApi {
+			fn apply_extrinsic(_extrinsic: yee_runtime::UncheckedExtrinsic) -> sp_runtime::ApplyExtrinsicResult { unimplemented!() }
+			fn finalize_block() -> RuntimeHeader { unimplemented!() }
+			fn inherent_extrinsics(_data: sp_inherents::InherentData) -> Vec<yee_runtime::UncheckedExtrinsic> { unimplemented!() }
+			// as the pow pallet, expected reward condition MUST match the declared one
+			fn check_inherents(&self, _block: yee_runtime::Block, data: sp_inherents::InherentData) -> sp_inherents::CheckInherentsResult {
+				let mut result = sp_inherents::CheckInherentsResult::new();
+				let info: PowInfo<[u8; 32]> = data.pow_inherent_data().unwrap();
+				let slash_expected = match info.reward_condition {
+					RewardCondition::Slash(_) => true,
+					RewardCondition::Normal => false,
+				};
+				if slash_expected != self.slash_declared {
+					let e = sp_inherents::MakeFatalError::from(());
+					result.put_error(yp_consensus_pow::inherents::INHERENT_IDENTIFIER, &e).unwrap();
+				}
+				result
+			}
+			fn random_seed() -> H256 { unimplemented!() }
+		}
+	}
+
+	impl ProvideRuntimeApi<yee_runtime::Block> for TestClient {
+		type Api = TestApi;
+
+		fn runtime_api<'a>(&'a self) -> sp_api::ApiRef<'a, Self::Api> {
+			TestApi { slash_declared: self.slash_declared }.into()
+		}
+	}
+
+	/// verifier of shard 0 of 1 on top of `parent`
+	fn test_verifier(parent: RuntimeHeader) -> PowVerifier<yee_runtime::Block, TestClient, [u8; 32], u64> {
+		let mut coinbase = [0u8; 32];
+		coinbase[31] = 1;
+		let inherent_data_providers = InherentDataProviders::new();
+		crate::register_inherent_data_provider(&inherent_data_providers, coinbase).unwrap();
+		PowVerifier {
+			client: Arc::new(TestClient { parent, slash_declared: true }),
+			inherent_data_providers,
+			phantom: PhantomData,
+			shard_extra: ShardExtra::new(coinbase, 0, 1, None, Arc::new(CountingRestart::default())),
+			context: Context {
+				genesis_pow_target: yp_consensus_pow::PowTarget::max_value(),
+				genesis_pow_target_adj: 10,
+				genesis_target_block_time: 30,
+				genesis_shard_count: 1,
+				// observe window is read from the runtime, 10 in TestApi
+				genesis_scale_out_observe_blocks: 5,
+				pow_engine_id: yp_consensus_pow::YEE_POW_ENGINE_ID,
+				max_proof_extrinsics: 16,
+			},
+			digest_limit: DigestLimit::default(),
+			import_errors: Default::default(),
+		}
+	}
+
+	#[test]
+	fn test_verify_bad_relay_body() {
+		use sp_runtime::traits::Hash;
+		use yee_runtime::{Call, TimestampCall, UncheckedExtrinsic};
+
+		let body = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(42)))];
+		let root = BlakeTwo256::ordered_trie_root(body.iter().map(Encode::encode).collect());
+
+		let mut parent = RuntimeHeader::new(0, Default::default(), Default::default(), Default::default(), Default::default());
+		parent.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(0, 1));
+		let mut header = RuntimeHeader::new(1, root, Default::default(), parent.hash(), Default::default());
+		header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(0, 1));
+		let (relay_proof, _) = gen_extrinsic_proof::<yee_runtime::Block>(&header, &body, 16).unwrap();
+
+		let sealed = |relay_proof: H256| {
+			let seal = PowSeal::<yee_runtime::Block, u64> {
+				authority_id: 0,
+				pow_target: yp_consensus_pow::PowTarget::max_value(),
+				timestamp: 1,
+				work_proof: nonce_proof(1),
+				relay_proof,
+			};
+			let mut header = header.clone();
+			header.digest_mut().push(<DigestItem<H256> as CompatibleDigestItem<yee_runtime::Block, u64>>::pow_seal(seal));
+			header
+		};
+
+		let mut verifier = test_verifier(parent);
+
+		assert!(verifier.verify(BlockOrigin::NetworkBroadcast, sealed(relay_proof), None, Some(body.clone())).is_ok());
+
+		// body not matching extrinsics root is rejected
+		let other = vec![UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(43)))];
+		let e = verifier.verify(BlockOrigin::NetworkBroadcast, sealed(relay_proof), None, Some(other)).err();
+		assert!(e.unwrap().starts_with("Invalid relay in block 1"));
+		assert_eq!(verifier.import_errors.get("extrinsics_root"), 1);
+
+		// relay proof not matching body slashes the block instead
+		let bad: H256 = [1u8; 32].into();
+		assert!(verifier.verify(BlockOrigin::NetworkBroadcast, sealed(bad), None, Some(body.clone())).is_ok());
+		assert_eq!(verifier.import_errors.get("proof_mismatch"), 0);
+
+		// only if the block declares its slash
+		let parent = verifier.client.parent.clone();
+		verifier.client = Arc::new(TestClient { parent, slash_declared: false });
+		let e = verifier.verify(BlockOrigin::NetworkBroadcast, sealed(bad), None, Some(body)).err();
+		assert!(e.unwrap().starts_with("Slashed block"));
+	}
+
+	#[test]
+	fn test_relay_reward_condition() {
+		assert_eq!(relay_reward_condition::<u32>(Ok(())), Ok(RewardCondition::Normal));
+
+		let mismatch = ImportError::InvalidRelay {
+			block: 7,
+			reason: RelayRejectReason::ProofMismatch { seal: [1u8; 32].into(), body: Default::default() },
+		};
+		assert_eq!(relay_reward_condition(Err(mismatch)), Ok(RewardCondition::Slash(SlashReason::InvalidRelayProof)));
+
+		// body inconsistent with header is still rejected
+		let root = ImportError::InvalidRelay { block: 7u32, reason: RelayRejectReason::ExtrinsicsRoot("mismatch".to_string()) };
+		assert_eq!(relay_reward_condition(Err(root.clone())), Err(root));
+	}
+
+	#[test]
+	fn test_check_shard_info_started_window() {
+		let with_phase = |number: u32, phase: ScaleOutPhase<u32, u16>| {
+			let mut header = RuntimeHeader::new(number, Default::default(), Default::default(), Default::default(), Default::default());
+			header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(0, 1));
+			header.digest_mut().push(<DigestItem<H256> as ScaleOutPhaseDigestItem<u32, u16>>::scale_out_phase(phase));
+			header
+		};
+		let started = |observe_util: u32| ScaleOutPhase::Started { observe_util, shard_num: 1 };
+		let verifier = test_verifier(with_phase(19, started(20)));
+
+		// within the window
+		assert!(verifier.check_shard_info(&with_phase(15, started(20))).is_ok());
+		assert!(verifier.check_shard_info(&with_phase(20, started(20))).is_err());
+
+		// not ready at the end of the window, observe another one
+		assert!(verifier.check_shard_info(&with_phase(20, started(30))).is_ok());
+		assert!(verifier.check_shard_info(&with_phase(20, started(25))).is_err());
+		assert!(verifier.check_shard_info(&with_phase(21, started(31))).is_err());
+
+		// ready at the end of the window
+		let native_ready = |number: u32, observe_util: u32| with_phase(number, ScaleOutPhase::NativeReady { observe_util, shard_num: 1 });
+		assert!(verifier.check_shard_info(&native_ready(20, 30)).is_ok());
+		assert!(verifier.check_shard_info(&native_ready(19, 29)).is_err());
+	}
+
+	#[derive(Default)]
+	struct CountingRestart(std::sync::atomic::AtomicUsize);
+
+	impl crate::TriggerExit for CountingRestart {
+		fn trigger_restart(&self) {
+			self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+		}
+
+		fn trigger_stop(&self) {}
+	}
+
+	#[test]
+	fn test_committed_scale_out_restarts_once() {
+		use yp_sharding::ScaleOut;
+
+		let restart = Arc::new(CountingRestart::default());
+		let mut coinbase = [0u8; 32];
+		// shard 3 of 4
+		coinbase[31] = 0b11;
+		let shard_extra = ShardExtra::new(coinbase, 1, 2, Some(ScaleOut { shard_num: 3 }), restart.clone());
+		// as handed to start_pow and import_queue
+		let (worker_extra, verifier_extra) = (shard_extra.clone(), shard_extra);
+
+		let committed = |number: u32| {
+			let mut header = RuntimeHeader::new(number, Default::default(), Default::default(), Default::default(), Default::default());
+			header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(3, 4));
+			header.digest_mut().push(<DigestItem<H256> as ScaleOutPhaseDigestItem<u32, u16>>::scale_out_phase(
+				ScaleOutPhase::Committed { shard_num: 3, shard_count: 4 }
+			));
+			header
+		};
+
+		// seen by worker and verifier, then on a fork
+		assert!(check_scale::<yee_runtime::Block, [u8; 32]>(&committed(10), worker_extra.clone()).is_err());
+		for number in &[10, 11] {
+			assert!(check_scale::<yee_runtime::Block, [u8; 32]>(&committed(*number), verifier_extra.clone()).is_err());
+		}
+		assert_eq!(restart.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+		// no scale out phase, no restart
+		let mut header = RuntimeHeader::new(12, Default::default(), Default::default(), Default::default(), Default::default());
+		header.digest_mut().push(<DigestItem<H256> as ShardingDigestItem<u16>>::sharding_info(1, 2));
+		assert!(check_scale::<yee_runtime::Block, [u8; 32]>(&header, verifier_extra).is_ok());
+		assert_eq!(restart.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+	}
 
 	#[test]
 	fn test_get_original_shard_num() {
@@ -21,15 +21,20 @@ use sp_core::H256;
 
 use {
 	futures::{
+		channel::mpsc as future_mpsc,
 		future::{self, Either},
 		Future,
 		prelude::*,
 	},
-	log::{info, warn},
+	log::{error, info, warn},
 	std::{
+		collections::HashMap,
 		fmt::Debug,
+		hash::Hash,
 		marker::PhantomData,
-		sync::{Arc, RwLock},
+		ops::Range,
+		sync::{Arc, RwLock, atomic::{AtomicBool, Ordering}, mpsc},
+		thread,
 		time::{Duration, Instant},
 	},
 };
@@ -40,6 +45,7 @@ use {
 	},
 	sp_inherents::InherentDataProviders,
 	sp_runtime::{
+		ConsensusEngineId,
 		codec::{Codec, Decode, Encode},
 		traits::{
 			Block,
@@ -50,8 +56,8 @@ use {
 use yc_sharding::{ScaleOutPhaseDigestItem, ShardingDigestItem};
 
 use crate::job::{DefaultJob, JobManager};
-use crate::pow::check_work_proof;
-use crate::ShardExtra;
+use crate::pow::{check_work_proof, NONCE_PREFIX, PowSeal};
+use crate::{ShardExtra, TriggerExit};
 use crate::verifier::check_scale;
 use futures_timer::Delay;
 
@@ -75,12 +81,54 @@ pub trait PowWorker<JM: JobManager> {
 	fn on_work(&self, iter: u64) -> Self::OnWork;
 }
 
+/// Generates nonce proofs tried by the worker for a job
+pub trait NonceStrategy: Send + Sync {
+	/// nonce proof tried at `index` of a job mined on `shard_num`
+	///
+	/// Must be O(1) in `index`, a job resumed after `index` nonces seeks straight to it.
+	fn nonce(&self, shard_num: u16, index: u64) -> ProofNonce;
+}
+
+/// Tries nonces 0, 1, 2, ... for each job
+#[derive(Default)]
+pub struct SequentialNonce;
+
+impl NonceStrategy for SequentialNonce {
+	fn nonce(&self, shard_num: u16, index: u64) -> ProofNonce {
+		ProofNonce::get_with_shard(NONCE_PREFIX, shard_num, 12, index)
+	}
+}
+
+/// Reserve the next `iter` nonce indexes for the job `job_key`,
+/// resuming after the nonces tried for it in previous rounds.
+///
+/// Jobs are keyed by parent hash and shard rather than pre hash, which changes with the timestamp.
+/// Nonces tried for other jobs are forgotten, those jobs are stale.
+fn take_nonces<K: Hash + Eq>(
+	tried: &mut HashMap<K, u64>,
+	job_key: K,
+	iter: u64,
+) -> Range<u64> {
+	let start = tried.get(&job_key).cloned().unwrap_or(0);
+	let end = start.saturating_add(iter);
+	tried.retain(|key, _| key == &job_key);
+	tried.insert(job_key, end);
+	start..end
+}
+
 pub struct DefaultWorker<B, I, JM, AccountId, AuthorityId> {
 	job_manager: Arc<JM>,
 	block_import: Arc<Mutex<I>>,
 	inherent_data_providers: InherentDataProviders,
 	stop_sign: Arc<RwLock<bool>>,
 	shard_extra: ShardExtra<AccountId>,
+	engine_id: ConsensusEngineId,
+	nonce_strategy: Arc<dyn NonceStrategy>,
+	num_threads: usize,
+	/// nonces tried per job parent hash and shard
+	tried_nonces: Arc<Mutex<HashMap<(B::Hash, u16), u64>>>,
+	/// spawned on first work round, after builder settings are applied
+	search_pool: Mutex<Option<Arc<SearchPool<B, AuthorityId>>>>,
 	phantom: PhantomData<(B, AuthorityId)>,
 }
 
@@ -93,6 +141,7 @@ impl<B, I, JM, AccountId, AuthorityId> DefaultWorker<B, I, JM, AccountId, Author
 		block_import: Arc<Mutex<I>>,
 		inherent_data_providers: InherentDataProviders,
 		shard_extra: ShardExtra<AccountId>,
+		engine_id: ConsensusEngineId,
 	) -> Self {
 		DefaultWorker {
 			job_manager,
@@ -100,9 +149,43 @@ impl<B, I, JM, AccountId, AuthorityId> DefaultWorker<B, I, JM, AccountId, Author
 			inherent_data_providers,
 			stop_sign: Default::default(),
 			shard_extra,
+			engine_id,
+			nonce_strategy: Arc::new(SequentialNonce),
+			num_threads: num_cpus::get(),
+			tried_nonces: Default::default(),
+			search_pool: Mutex::new(None),
 			phantom: PhantomData,
 		}
 	}
+
+	/// number of threads nonces are searched on, defaults to available cpus
+	pub fn with_num_threads(mut self, num_threads: usize) -> Self {
+		self.num_threads = num_threads.max(1);
+		self
+	}
+
+	/// replace the default sequential nonce strategy
+	pub fn with_nonce_strategy(mut self, nonce_strategy: Arc<dyn NonceStrategy>) -> Self {
+		self.nonce_strategy = nonce_strategy;
+		self
+	}
+}
+
+impl<B, I, JM, AccountId, AuthorityId> DefaultWorker<B, I, JM, AccountId, AuthorityId> where
+	B: Block,
+	AuthorityId: Decode + Encode + Send + Sync + Clone + 'static,
+	DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16>,
+{
+	/// search threads shared by all work rounds
+	fn search_pool(&self) -> Result<Arc<SearchPool<B, AuthorityId>>, sp_consensus::Error> {
+		let mut search_pool = self.search_pool.lock();
+		if let Some(pool) = search_pool.as_ref() {
+			return Ok(pool.clone());
+		}
+		let pool = Arc::new(SearchPool::new(self.num_threads, self.nonce_strategy.clone())?);
+		*search_pool = Some(pool.clone());
+		Ok(pool)
+	}
 }
 
 impl<B, I, JM, AccountId, AuthorityId> PowWorker<JM> for DefaultWorker<B, I, JM, AccountId, AuthorityId> where
@@ -134,76 +217,264 @@ impl<B, I, JM, AccountId, AuthorityId> PowWorker<JM> for DefaultWorker<B, I, JM,
 	fn on_work(&self,
 			   iter: u64,
 	) -> Self::OnWork {
-		let mut block_import = self.block_import.clone();
+		let block_import = self.block_import.clone();
 
 		let job = self.on_job().into_future();
 
 		let shard_extra = self.shard_extra.clone();
+		let engine_id = self.engine_id;
+		let tried_nonces = self.tried_nonces.clone();
+		let search_pool = match self.search_pool() {
+			Ok(search_pool) => search_pool,
+			Err(e) => return Box::pin(future::err(e)),
+		};
 
-		let on_proposal_block = move |job: DefaultJob<B, AuthorityId>| -> Result<(), sp_consensus::Error> {
+		let on_proposal_block = move |job: DefaultJob<B, AuthorityId>| {
 			let header = job.header;
 			let body = job.body;
 			let header_num = header.number().clone();
 			let header_pre_hash = header.hash();
 			let digest_item = job.digest_item;
 			let pow_target = digest_item.pow_target;
-			let xts_proof = job.xts_proof.clone();
 
 			info!("block template {} @ {:?}, pow target: {:#x}", header_num, header_pre_hash, pow_target);
 
-			// TODO: remove hardcoded
-			const PREFIX: &str = "yeeroot-";
-
-			for i in 0_u64..iter {
-				let shard_extra = shard_extra.clone();
-				let proof = WorkProof::Nonce(ProofNonce::get_with_prefix_len(PREFIX, 12, i));
-				let mut seal = digest_item.clone();
-				seal.work_proof = proof;
-
-				if let Ok((post_digest, hash)) = check_work_proof(&header, &seal) {
-					check_scale::<B, AccountId>(&header, shard_extra)?;
-
-					let mut import_block = BlockImportParams::new(BlockOrigin::Own, header);
-					import_block.post_digests.push(post_digest);
-					import_block.body = Some(body);
-					import_block.storage_changes = None;//TODO Some(storage_changes);
-					import_block.fork_choice = Some(ForkChoiceStrategy::LongestChain);
+			let shard_info: Option<(u16, u16)> = header.digest().logs().iter().rev()
+				.filter_map(ShardingDigestItem::as_sharding_info)
+				.next();
+			let shard_num = match shard_info {
+				Some((shard_num, _)) => shard_num,
+				None => {
+					warn!("block proposal failed: shard info not found");
+					return Either::Right(future::ok(()));
+				}
+			};
 
-					block_import.import_block(import_block, Default::default())?;
+			let nonces = take_nonces(&mut tried_nonces.lock(), (*header.parent_hash(), shard_num), iter);
 
-					info!("{} @ {} {:?}", Colour::Green.bold().paint("Block mined"), header_num, hash);
-					return Ok(());
+			// nonces are searched on the pool threads, off the executor
+			Either::Left(search_pool.search(&header, &digest_item, shard_num, nonces, engine_id).map(move |solution| {
+				if let Some(solution) = solution {
+					if let Err(e) = import_solution::<B, I, AccountId>(&block_import, header, body, solution, shard_extra) {
+						warn!("block proposal failed {:?}", e);
+					}
 				}
-			}
-
-			Ok(())
+				Ok(())
+			}))
 		};
 
-		Box::new(
+		Box::pin(
 			job
 				.map_err(|e| {
 					warn!("job error: {:?}", e);
 					e
 				})
 				.map_err(to_common_error)
-				.map(move |job| {
-					if let Err(e) = on_proposal_block(job) {
-						warn!("block proposal failed {:?}", e);
+				.and_then(on_proposal_block)
+		)
+	}
+}
+
+/// Import the block of `header` and `body` sealed by `solution` found by a work round
+fn import_solution<B, I, AccountId>(
+	block_import: &Mutex<I>,
+	header: B::Header,
+	body: Vec<B::Extrinsic>,
+	solution: (DigestItemFor<B>, B::Hash),
+	shard_extra: ShardExtra<AccountId>,
+) -> Result<(), sp_consensus::Error> where
+	B: Block,
+	I: BlockImport<B, Error=sp_consensus::Error>,
+	DigestItemFor<B>: ShardingDigestItem<u16> + ScaleOutPhaseDigestItem<NumberFor<B>, u16>,
+	AccountId: Codec + Clone,
+{
+	let (post_digest, hash) = solution;
+	let header_num = header.number().clone();
+	check_scale::<B, AccountId>(&header, shard_extra).map_err(sp_consensus::Error::ClientImport)?;
+
+	let mut import_block = BlockImportParams::new(BlockOrigin::Own, header);
+	import_block.post_digests.push(post_digest);
+	import_block.body = Some(body);
+	import_block.storage_changes = None;//TODO Some(storage_changes);
+	import_block.fork_choice = Some(ForkChoiceStrategy::LongestChain);
+
+	block_import.lock().import_block(import_block, Default::default())?;
+
+	info!("{} @ {} {:?}", Colour::Green.bold().paint("Block mined"), header_num, hash);
+	Ok(())
+}
+
+/// Nonce search of a work round, scanned by every thread of a `SearchPool`
+struct SearchTask<B: Block, AuthorityId> {
+	header: B::Header,
+	seal: PowSeal<B, AuthorityId>,
+	shard_num: u16,
+	nonces: Range<u64>,
+	engine_id: ConsensusEngineId,
+	/// set by the thread finding a solution, the others stop
+	found: Arc<AtomicBool>,
+	/// each thread sends one result per task, its solution or `None`
+	result: future_mpsc::UnboundedSender<Option<(DigestItemFor<B>, B::Hash)>>,
+}
+
+/// Long-lived nonce search threads fed one task per work round.
+///
+/// Thread `i` of `n` scans nonce indexes `start + i`, `start + i + n`, ... of each task,
+/// generating nonces from the strategy, nothing is allocated up front.
+/// Threads exit once the pool is dropped.
+pub struct SearchPool<B: Block, AuthorityId> {
+	tasks: Mutex<Vec<mpsc::Sender<SearchTask<B, AuthorityId>>>>,
+}
+
+impl<B, AuthorityId> SearchPool<B, AuthorityId> where
+	B: Block,
+	AuthorityId: Decode + Encode + Send + Sync + Clone + 'static,
+	DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16>,
+{
+	/// Spawn `num_threads` search threads
+	pub fn new(num_threads: usize, strategy: Arc<dyn NonceStrategy>) -> Result<Self, sp_consensus::Error> {
+		let num_threads = num_threads.max(1);
+		let tasks = (0..num_threads as u64).map(|offset| {
+			let (tx, rx) = mpsc::channel::<SearchTask<B, AuthorityId>>();
+			let strategy = strategy.clone();
+			thread::Builder::new()
+				.name(format!("pow-search-{}", offset))
+				.spawn(move || {
+					while let Ok(task) = rx.recv() {
+						let solution = search_stride(&task, strategy.as_ref(), offset, num_threads);
+						let _ = task.result.unbounded_send(solution);
 					}
 				})
-		)
+				.map(|_| tx)
+				.map_err(to_common_error)
+		}).collect::<Result<Vec<_>, _>>()?;
+		Ok(SearchPool { tasks: Mutex::new(tasks) })
+	}
+
+	/// Search nonce indexes `nonces` on all threads, resolves to the first solution found.
+	///
+	/// Resolves once every thread is done with the task, a round yields at most one solution.
+	pub fn search(
+		&self,
+		header: &B::Header,
+		seal: &PowSeal<B, AuthorityId>,
+		shard_num: u16,
+		nonces: Range<u64>,
+		engine_id: ConsensusEngineId,
+	) -> impl Future<Output=Option<(DigestItemFor<B>, B::Hash)>> {
+		let found = Arc::new(AtomicBool::new(false));
+		let (tx, rx) = future_mpsc::unbounded();
+		let sent = self.tasks.lock().iter().filter(|task| {
+			task.send(SearchTask {
+				header: header.clone(),
+				seal: seal.clone(),
+				shard_num,
+				nonces: nonces.clone(),
+				engine_id,
+				found: found.clone(),
+				result: tx.clone(),
+			}).is_ok()
+		}).count();
+		drop(tx);
+
+		rx.take(sent).fold(None, |solution, result| future::ready(solution.or(result)))
+	}
+}
+
+/// Scan the stride of thread `offset` of `num_threads` in the nonces of `task`
+fn search_stride<B, AuthorityId>(
+	task: &SearchTask<B, AuthorityId>,
+	strategy: &dyn NonceStrategy,
+	offset: u64,
+	num_threads: usize,
+) -> Option<(DigestItemFor<B>, B::Hash)> where
+	B: Block,
+	AuthorityId: Decode + Encode + Clone,
+	DigestItemFor<B>: CompatibleDigestItem<B, AuthorityId> + ShardingDigestItem<u16>,
+{
+	let mut seal = task.seal.clone();
+	let stride = task.nonces.start.saturating_add(offset)..task.nonces.end;
+	for index in stride.step_by(num_threads) {
+		if task.found.load(Ordering::Relaxed) {
+			return None;
+		}
+		seal.work_proof = WorkProof::Nonce(strategy.nonce(task.shard_num, index));
+		if let Ok(solution) = check_work_proof(&task.header, &seal, task.engine_id) {
+			// only the first solution is returned, so the block is imported once
+			if !task.found.swap(true, Ordering::SeqCst) {
+				return Some(solution);
+			}
+			return None;
+		}
 	}
+	None
 }
 
 pub fn to_common_error<E: Debug>(e: E) -> sp_consensus::Error {
 	sp_consensus::Error::ClientImport(format!("{:?}", e)).into()
 }
 
+/// Detects stalled mining, i.e. no work round finished within the timeout
+pub struct StallWatchdog {
+	timeout: Duration,
+	restart: bool,
+	trigger_exit: Arc<dyn TriggerExit>,
+	last_progress: Mutex<Instant>,
+}
+
+impl StallWatchdog {
+	/// timeout is `multiplier` times the target block time
+	pub fn new(target_block_time: Duration, multiplier: u32, restart: bool, trigger_exit: Arc<dyn TriggerExit>) -> Self {
+		StallWatchdog {
+			timeout: target_block_time * multiplier,
+			restart,
+			trigger_exit,
+			last_progress: Mutex::new(Instant::now()),
+		}
+	}
+
+	pub fn timeout(&self) -> Duration {
+		self.timeout
+	}
+
+	/// record a finished work round
+	pub fn on_progress(&self) {
+		*self.last_progress.lock() = Instant::now();
+	}
+
+	/// returns true if mining stalled since last progress
+	pub fn check(&self) -> bool {
+		let mut last_progress = self.last_progress.lock();
+		let elapsed = last_progress.elapsed();
+		if elapsed < self.timeout {
+			return false;
+		}
+
+		error!("{}: no job finished in {:?}", Colour::Red.paint("Mining stalled"), elapsed);
+		if self.restart {
+			warn!("Restart service for stalled mining");
+			self.trigger_exit.trigger_restart();
+		}
+		// report once per timeout
+		*last_progress = Instant::now();
+		true
+	}
+}
+
+/// Nonces tried per job before a new job is fetched
+pub const DEFAULT_MINING_ITERATIONS: u64 = 10000;
+
+/// Wait before retrying while the node is major syncing
+pub const DEFAULT_JOB_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
 pub fn start_worker<W, SO, JM, OnExit>(
 	worker: Arc<W>,
 	sync_oracle: SO,
 	on_exit: OnExit,
 	mine: bool,
+	mining_iterations: u64,
+	job_refresh_interval: Duration,
+	watchdog: Arc<StallWatchdog>,
 ) -> Result<impl Future<Output=()>, sp_consensus::Error> where
 	W: PowWorker<JM>,
 	SO: SyncOracle,
@@ -215,8 +486,9 @@ pub fn start_worker<W, SO, JM, OnExit>(
 	let stop_sign = worker.stop_sign();
 
 	info!("worker loop start");
+	let check_watchdog = watchdog.clone();
 	let work = loop_fn((), move |()| {
-		let delay = Delay::new(Duration::from_secs(5));
+		let delay = Delay::new(job_refresh_interval);
 		let delayed_continue = Either::Left(delay.then(|_| future::ok(Loop::Continue(()))));
 		let no_delay_stop = Either::Right(future::ok(Loop::Break(())));
 
@@ -240,17 +512,30 @@ pub fn start_worker<W, SO, JM, OnExit>(
 		info!("worker one loop start");
 
 		if sync_oracle.is_major_syncing() {
+			watchdog.on_progress();
 			return Either::Left(delayed_continue);
 		}
 
-		let task = worker.on_work(10000).into_future();
+		let watchdog = watchdog.clone();
+		let task = worker.on_work(mining_iterations).into_future();
 		Either::Right(
-			task.then(|_| Delay::new(Duration::from_secs(0)))
+			task.then(move |_| {
+				watchdog.on_progress();
+				Delay::new(Duration::from_secs(0))
+			})
 				.then(|_| future::ok(Loop::Continue(())))
 		)
 	});
 
-	Ok(work.select(on_exit).then(move |_| {
+	let watch = loop_fn((), move |()| {
+		let watchdog = check_watchdog.clone();
+		Delay::new(watchdog.timeout()).then(move |_| {
+			watchdog.check();
+			future::ok::<_, ()>(Loop::Continue(()))
+		})
+	});
+
+	Ok(work.select(watch).select(on_exit).then(move |_| {
 		stop_sign.write()
 			.map(|mut sign| { *sign = true; })
 			.unwrap_or_else(|e| { warn!("write stop sign error : {:?}", e); });
@@ -307,10 +592,311 @@ impl<S, T, A, F, E> Future for LoopFn<A, F>
 						Ok(Loop::Continue(s)) => this.future = (this.func)(s),
 						Err(e) => return Poll::Ready(Err(e)),
 					},
-					Poll::NotReady => return Poll::NotReady,
+					Poll::Pending => return Poll::Pending,
 				}
 				self = Pin::new_unchecked(this);
 			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use super::*;
+
+	#[derive(Default)]
+	pub struct CountingTriggerExit {
+		pub restart: AtomicUsize,
+		pub stop: AtomicUsize,
+	}
+
+	impl TriggerExit for CountingTriggerExit {
+		fn trigger_restart(&self) {
+			self.restart.fetch_add(1, Ordering::SeqCst);
+		}
+
+		fn trigger_stop(&self) {
+			self.stop.fetch_add(1, Ordering::SeqCst);
+		}
+	}
+
+	/// splitmix64 nonces, for tests only
+	struct RandomNonce {
+		seed: u64,
+	}
+
+	impl NonceStrategy for RandomNonce {
+		fn nonce(&self, shard_num: u16, index: u64) -> ProofNonce {
+			let mut z = self.seed.wrapping_add(index.wrapping_mul(0x9E3779B97F4A7C15));
+			z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+			z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+			ProofNonce::get_with_shard(NONCE_PREFIX, shard_num, 12, z ^ (z >> 31))
+		}
+	}
+
+	#[test]
+	fn test_sequential_nonce() {
+		let strategy = SequentialNonce;
+		assert_eq!(strategy.nonce(3, 0).nonce, 0);
+		assert_eq!(strategy.nonce(3, 1).nonce, 1);
+		assert_eq!(strategy.nonce(3, 1 << 40).nonce, 1 << 40);
+		let proof = strategy.nonce(3, 0);
+		assert_eq!(proof.shard_num(NONCE_PREFIX), Some(3));
+	}
+
+	#[test]
+	fn test_take_nonces_resumes_job() {
+		let mut tried = HashMap::new();
+
+		// same job, non overlapping ranges
+		assert_eq!(take_nonces(&mut tried, (1u8, 1u16), 10), 0..10);
+		assert_eq!(take_nonces(&mut tried, (1u8, 1u16), 10), 10..20);
+
+		// new job starts over, stale job dropped
+		assert_eq!(take_nonces(&mut tried, (2u8, 1u16), 10), 0..10);
+		assert_eq!(tried.len(), 1);
+		assert_eq!(take_nonces(&mut tried, (1u8, 1u16), 10), 0..10);
+
+		// same parent on another shard is another job
+		assert_eq!(take_nonces(&mut tried, (1u8, 3u16), 10), 0..10);
+	}
+
+	#[test]
+	fn test_random_nonce() {
+		use std::collections::HashSet;
+		use yee_runtime::{Block as RuntimeBlock, DigestItem, Header as RuntimeHeader};
+		use yp_consensus_pow::{PowTarget, YEE_POW_ENGINE_ID};
+
+		let mut header = RuntimeHeader::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+		header.digest_mut().push(<DigestItem as ShardingDigestItem<u16>>::sharding_info(2, 4));
+
+		let strategy: Arc<dyn NonceStrategy> = Arc::new(RandomNonce { seed: 0x2545F4914F6CDD1D });
+		let mut nonces = HashSet::new();
+		for index in 0..100 {
+			let proof = strategy.nonce(2, index);
+			assert!(nonces.insert(proof.nonce));
+
+			let seal = PowSeal::<RuntimeBlock, u64> {
+				authority_id: 0,
+				pow_target: PowTarget::max_value(),
+				timestamp: 0,
+				work_proof: WorkProof::Nonce(proof),
+				relay_proof: Default::default(),
+			};
+			assert!(check_work_proof(&header, &seal, YEE_POW_ENGINE_ID).is_ok());
+		}
+	}
+
+	#[test]
+	fn test_search_pool_multi_threaded() {
+		use yee_runtime::{Block as RuntimeBlock, DigestItem, Header as RuntimeHeader};
+		use yp_consensus_pow::{PowTarget, YEE_POW_ENGINE_ID};
+
+		let mut header = RuntimeHeader::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+		header.digest_mut().push(<DigestItem as ShardingDigestItem<u16>>::sharding_info(2, 4));
+
+		let strategy: Arc<dyn NonceStrategy> = Arc::new(SequentialNonce);
+		let pool = SearchPool::<RuntimeBlock, u64>::new(4, strategy.clone()).unwrap();
+
+		// every nonce is a solution, threads race for it
+		let seal = PowSeal::<RuntimeBlock, u64> {
+			authority_id: 0,
+			pow_target: PowTarget::max_value(),
+			timestamp: 0,
+			work_proof: WorkProof::Unknown,
+			relay_proof: Default::default(),
+		};
+		let search = pool.search(&header, &seal, 2, 0..1000, YEE_POW_ENGINE_ID);
+		let (_, hash) = futures::executor::block_on(search).unwrap();
+
+		// the winning nonce is one of the searched ones
+		let winner = (0..1000).find(|index| {
+			let mut seal = seal.clone();
+			seal.work_proof = WorkProof::Nonce(strategy.nonce(2, *index));
+			check_work_proof(&header, &seal, YEE_POW_ENGINE_ID).map(|(_, h)| h) == Ok(hash)
+		});
+		assert!(winner.is_some());
+
+		// same threads serve later rounds: no solution below an impossible target
+		let mut hard = seal.clone();
+		hard.pow_target = PowTarget::zero();
+		let search = pool.search(&header, &hard, 2, 1000..1100, YEE_POW_ENGINE_ID);
+		assert!(futures::executor::block_on(search).is_none());
+
+		// more threads than nonces
+		let search = pool.search(&header, &seal, 2, 5..7, YEE_POW_ENGINE_ID);
+		assert!(futures::executor::block_on(search).is_some());
+	}
+
+	/// counts imported blocks
+	#[derive(Default)]
+	struct CountingImport {
+		imported: Arc<AtomicUsize>,
+	}
+
+	impl BlockImport<yee_runtime::Block> for CountingImport {
+		type Error = sp_consensus::Error;
+		type Transaction = ();
+
+		fn check_block(
+			&mut self,
+			_block: sp_consensus::BlockCheckParams<yee_runtime::Block>,
+		) -> Result<sp_consensus::ImportResult, Self::Error> {
+			Ok(sp_consensus::ImportResult::imported(false))
+		}
+
+		fn import_block(
+			&mut self,
+			_block: BlockImportParams<yee_runtime::Block, ()>,
+			_cache: HashMap<sp_consensus::import_queue::CacheKeyId, Vec<u8>>,
+		) -> Result<sp_consensus::ImportResult, Self::Error> {
+			self.imported.fetch_add(1, Ordering::SeqCst);
+			Ok(sp_consensus::ImportResult::imported(false))
+		}
+	}
+
+	#[test]
+	fn test_search_pool_imports_once() {
+		use yee_runtime::{Block as RuntimeBlock, DigestItem, Header as RuntimeHeader};
+		use yp_consensus_pow::{PowTarget, YEE_POW_ENGINE_ID};
+
+		let mut header = RuntimeHeader::new(1, Default::default(), Default::default(), Default::default(), Default::default());
+		header.digest_mut().push(<DigestItem as ShardingDigestItem<u16>>::sharding_info(2, 4));
+
+		let block_import = CountingImport::default();
+		let imported = block_import.imported.clone();
+		let block_import = Mutex::new(block_import);
+		let shard_extra = ShardExtra::new([0u8; 32], 2, 4, None, Arc::new(CountingTriggerExit::default()));
+
+		let pool = SearchPool::<RuntimeBlock, u64>::new(4, Arc::new(SequentialNonce)).unwrap();
+		let seal = PowSeal::<RuntimeBlock, u64> {
+			authority_id: 0,
+			pow_target: PowTarget::max_value(),
+			timestamp: 0,
+			work_proof: WorkProof::Unknown,
+			relay_proof: Default::default(),
+		};
+
+		// all four threads find solutions, as in on_work only the winner is imported
+		let search = pool.search(&header, &seal, 2, 0..1000, YEE_POW_ENGINE_ID).map(|solution| {
+			if let Some(solution) = solution {
+				import_solution::<RuntimeBlock, _, [u8; 32]>(&block_import, header.clone(), vec![], solution, shard_extra.clone()).unwrap();
+			}
+		});
+		futures::executor::block_on(search);
+		assert_eq!(imported.load(Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn test_stall_watchdog() {
+		let trigger_exit = Arc::new(CountingTriggerExit::default());
+		let watchdog = StallWatchdog::new(Duration::from_millis(10), 2, true, trigger_exit.clone());
+		assert_eq!(watchdog.timeout(), Duration::from_millis(20));
+
+		assert!(!watchdog.check());
+
+		// proposer hangs, no progress reported
+		std::thread::sleep(Duration::from_millis(30));
+		assert!(watchdog.check());
+		assert_eq!(trigger_exit.restart.load(Ordering::SeqCst), 1);
+
+		watchdog.on_progress();
+		assert!(!watchdog.check());
+		assert_eq!(trigger_exit.restart.load(Ordering::SeqCst), 1);
+	}
+
+	struct NoJob;
+
+	impl crate::job::Job for NoJob {
+		type Hash = ();
+	}
+
+	struct NoJobManager;
+
+	impl JobManager for NoJobManager {
+		type Job = NoJob;
+
+		fn get_job(&mut self) -> Box<dyn Future<Output=Result<NoJob, sp_consensus::Error>> + Send> {
+			Box::new(future::pending())
+		}
+
+		fn submit_job(&self, _job: NoJob) -> Box<dyn Future<Output=Result<(), sp_consensus::Error>> + Send> {
+			Box::new(future::ok(()))
+		}
+	}
+
+	/// records iterations of each work round, stops after the first one
+	#[derive(Default)]
+	struct RecordingWorker {
+		stop_sign: Arc<RwLock<bool>>,
+		iters: Mutex<Vec<u64>>,
+	}
+
+	impl PowWorker<NoJobManager> for RecordingWorker {
+		type Error = sp_consensus::Error;
+		type OnJob = future::Pending<Result<NoJob, Self::Error>>;
+		type OnWork = future::Ready<Result<(), Self::Error>>;
+
+		fn stop_sign(&self) -> Arc<RwLock<bool>> {
+			self.stop_sign.clone()
+		}
+
+		fn on_start(&self) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn on_job(&self) -> Self::OnJob {
+			future::pending()
+		}
+
+		fn on_work(&self, iter: u64) -> Self::OnWork {
+			self.iters.lock().push(iter);
+			*self.stop_sign.write().unwrap() = true;
+			future::ok(())
+		}
+	}
+
+	struct NotSyncing;
+
+	impl SyncOracle for NotSyncing {
+		fn is_major_syncing(&mut self) -> bool {
+			false
+		}
+
+		fn is_offline(&mut self) -> bool {
+			false
+		}
+	}
+
+	#[test]
+	fn test_mining_iterations_reach_on_work() {
+		let worker = Arc::new(RecordingWorker::default());
+		let watchdog = Arc::new(StallWatchdog::new(
+			Duration::from_secs(60), 1, false, Arc::new(CountingTriggerExit::default()),
+		));
+		let work = start_worker::<_, _, NoJobManager, _>(
+			worker.clone(),
+			NotSyncing,
+			future::pending(),
+			true,
+			42,
+			Duration::from_millis(1),
+			watchdog,
+		).unwrap();
+
+		futures::executor::block_on(work);
+		assert_eq!(*worker.iters.lock(), vec![42]);
+	}
+
+	#[test]
+	fn test_stall_watchdog_without_restart() {
+		let trigger_exit = Arc::new(CountingTriggerExit::default());
+		let watchdog = StallWatchdog::new(Duration::from_millis(5), 1, false, trigger_exit.clone());
+
+		std::thread::sleep(Duration::from_millis(10));
+		assert!(watchdog.check());
+		assert_eq!(trigger_exit.restart.load(Ordering::SeqCst), 0);
+	}
+}
@@ -18,6 +18,7 @@
 pub mod identify_specialization;
 use codec::{Encode, Decode};
 use sp_runtime::generic::DigestItem;
+use yp_sharding::ScaleOutPhase as RuntimeScaleOutPhase;
 
 /// Generated module index in construct_runtime!
 /// module specific log entries are prefixed by it and
@@ -85,7 +86,7 @@ impl<BlockNumber, ShardNum, Hash> ScaleOutPhaseDigestItem<BlockNumber, ShardNum>
 	}
 }
 
-#[derive(Encode, Decode, Debug, Clone)]
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub enum ScaleOutPhase<BlockNumber, ShardNum>{
 	Started{
 		observe_util: BlockNumber,
@@ -107,3 +108,53 @@ pub enum ScaleOutPhase<BlockNumber, ShardNum>{
 		shard_count: ShardNum,
 	},
 }
+
+impl<BlockNumber, ShardNum> From<RuntimeScaleOutPhase<BlockNumber, ShardNum>> for ScaleOutPhase<BlockNumber, ShardNum> {
+	fn from(phase: RuntimeScaleOutPhase<BlockNumber, ShardNum>) -> Self {
+		match phase {
+			RuntimeScaleOutPhase::Started { observe_util, shard_num } => ScaleOutPhase::Started { observe_util, shard_num },
+			RuntimeScaleOutPhase::NativeReady { observe_util, shard_num } => ScaleOutPhase::NativeReady { observe_util, shard_num },
+			RuntimeScaleOutPhase::Ready { observe_util, shard_num } => ScaleOutPhase::Ready { observe_util, shard_num },
+			RuntimeScaleOutPhase::Commiting { shard_count } => ScaleOutPhase::Committing { shard_count },
+			RuntimeScaleOutPhase::Committed { shard_num, shard_count } => ScaleOutPhase::Committed { shard_num, shard_count },
+		}
+	}
+}
+
+impl<BlockNumber, ShardNum> From<ScaleOutPhase<BlockNumber, ShardNum>> for RuntimeScaleOutPhase<BlockNumber, ShardNum> {
+	fn from(phase: ScaleOutPhase<BlockNumber, ShardNum>) -> Self {
+		match phase {
+			ScaleOutPhase::Started { observe_util, shard_num } => RuntimeScaleOutPhase::Started { observe_util, shard_num },
+			ScaleOutPhase::NativeReady { observe_util, shard_num } => RuntimeScaleOutPhase::NativeReady { observe_util, shard_num },
+			ScaleOutPhase::Ready { observe_util, shard_num } => RuntimeScaleOutPhase::Ready { observe_util, shard_num },
+			ScaleOutPhase::Committing { shard_count } => RuntimeScaleOutPhase::Commiting { shard_count },
+			ScaleOutPhase::Committed { shard_num, shard_count } => RuntimeScaleOutPhase::Committed { shard_num, shard_count },
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use codec::Encode;
+
+	use super::{RuntimeScaleOutPhase, ScaleOutPhase};
+
+	#[test]
+	fn test_scale_out_phase_round_trip() {
+		let phases: Vec<RuntimeScaleOutPhase<u32, u16>> = vec![
+			RuntimeScaleOutPhase::Started { observe_util: 10, shard_num: 5 },
+			RuntimeScaleOutPhase::NativeReady { observe_util: 20, shard_num: 5 },
+			RuntimeScaleOutPhase::Ready { observe_util: 30, shard_num: 5 },
+			RuntimeScaleOutPhase::Commiting { shard_count: 8 },
+			RuntimeScaleOutPhase::Committed { shard_num: 5, shard_count: 8 },
+		];
+
+		for phase in phases {
+			let client_phase: ScaleOutPhase<u32, u16> = phase.clone().into();
+			// digest items are decoded on client side from runtime encoding
+			assert_eq!(client_phase.encode(), phase.encode());
+			let runtime_phase: RuntimeScaleOutPhase<u32, u16> = client_phase.into();
+			assert_eq!(runtime_phase, phase);
+		}
+	}
+}
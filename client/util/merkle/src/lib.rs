@@ -61,6 +61,19 @@ impl<H: HashT> Algorithm<ProofHash<H>> for ProofAlgorithm<H> {
     }
 }
 
+/// Leading byte of proof bytes not compressed.
+///
+/// Flags never start a legacy unflagged proof, whose first byte is the `Option` tag 0x00 or 0x01.
+pub const PROOF_FLAG_RAW: u8 = 0xF0;
+/// Leading byte of proof bytes with zero runs compressed
+pub const PROOF_FLAG_ZERO_RUN: u8 = 0xF1;
+
+/// Max bytes a compressed proof decompresses to.
+///
+/// Twice the largest full proof, 65536 extrinsic hashes in layer one and 65536 shards in layer two
+/// take about 8 MiB as merkle trees.
+pub const MAX_DECOMPRESSED_PROOF_LEN: usize = 16 * 1024 * 1024;
+
 #[derive(Debug, Default, Clone, Encode, Decode)]
 pub struct MultiLayerProof {
     pub layer2_merkle: Option<MerkleTree<ProofHash<BlakeTwo256>, ProofAlgorithm<BlakeTwo256>>>,
@@ -119,6 +132,38 @@ impl MultiLayerProof {
         Decode::decode(&mut &bytes[..]).map_err(|_|())
     }
 
+    /// Turns a MultiLayerProof into raw bytes led by a flag byte.
+    ///
+    /// When `compress` is set, zero runs (hashes of empty shards) are compressed
+    /// if that makes the bytes smaller.
+    pub fn into_flagged_bytes(&self, compress: bool) -> Vec<u8> {
+        let raw = self.into_bytes();
+        if compress {
+            let compressed = zero_run_encode(&raw);
+            if compressed.len() < raw.len() {
+                let mut bytes = vec![PROOF_FLAG_ZERO_RUN];
+                bytes.extend(compressed);
+                return bytes;
+            }
+        }
+        let mut bytes = vec![PROOF_FLAG_RAW];
+        bytes.extend(raw);
+        bytes
+    }
+
+    /// Tries to parse `bytes` led by a flag byte into MultiLayerProof.
+    ///
+    /// Bytes without a flag are legacy proofs from `into_bytes`, stored before flags or sent by older nodes.
+    pub fn from_flagged_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        match bytes.split_first() {
+            Some((&PROOF_FLAG_RAW, raw)) => Self::from_bytes(raw),
+            Some((&PROOF_FLAG_ZERO_RUN, compressed)) =>
+                Self::from_bytes(&zero_run_decode(compressed, MAX_DECOMPRESSED_PROOF_LEN)?),
+            Some(_) => Self::from_bytes(bytes),
+            None => Err(()),
+        }
+    }
+
     /// Layer two merkle root.
     pub fn layer2_root(&self) -> Option<ProofHash<BlakeTwo256>> {
         if let Some(tree) = self.layer2_merkle.as_ref() {
@@ -148,4 +193,125 @@ impl MultiLayerProof {
         }
         return false;
     }
-}
\ No newline at end of file
+}
+
+/// Compress runs of zero bytes, each run becomes `0x00` followed by the run length
+fn zero_run_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == 0 {
+            let mut run = 1;
+            while run < 255 && i + run < data.len() && data[i + run] == 0 {
+                run += 1;
+            }
+            out.push(0);
+            out.push(run as u8);
+            i += run;
+        } else {
+            out.push(data[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Decompress zero runs, fails if output exceeds `max_len` bytes
+fn zero_run_decode(data: &[u8], max_len: usize) -> Result<Vec<u8>, ()> {
+    let mut out = Vec::with_capacity((data.len() * 2).min(max_len));
+    let mut iter = data.iter();
+    while let Some(&b) = iter.next() {
+        if b == 0 {
+            match iter.next() {
+                Some(&run) if run > 0 => out.resize(out.len() + run as usize, 0),
+                _ => return Err(()),
+            }
+        } else {
+            out.push(b);
+        }
+        if out.len() > max_len {
+            return Err(());
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::FromIterator;
+
+    use sp_core::H256;
+
+    use super::*;
+
+    fn sparse_proof(shard_count: u16) -> MultiLayerProof {
+        let mut layer1_merkles = Vec::new();
+        let mut layer2_leaves = Vec::new();
+        for i in 0..shard_count {
+            if i == 1 {
+                let tree = MerkleTree::from_iter(vec![H256::repeat_byte(1), H256::repeat_byte(2)]);
+                layer2_leaves.push(tree.root());
+                layer1_merkles.push((i, Some(tree)));
+            } else {
+                layer2_leaves.push(H256::default());
+                layer1_merkles.push((i, None));
+            }
+        }
+        let layer2_tree = MerkleTree::<ProofHash<BlakeTwo256>, ProofAlgorithm<BlakeTwo256>>::new(layer2_leaves);
+        MultiLayerProof::new_with_layer2(layer2_tree, layer1_merkles)
+    }
+
+    #[test]
+    fn test_zero_run() {
+        let data = [vec![1u8, 0, 2], vec![0u8; 300], vec![3u8]].concat();
+        let encoded = zero_run_encode(&data);
+        assert_eq!(encoded, vec![1, 0, 1, 2, 0, 255, 0, 45, 3]);
+        assert_eq!(zero_run_decode(&encoded, 1024), Ok(data.clone()));
+        assert_eq!(zero_run_decode(&[1, 0], 1024), Err(()));
+        assert_eq!(zero_run_decode(&[0, 0], 1024), Err(()));
+
+        // output bounded
+        assert_eq!(zero_run_decode(&encoded, data.len()), Ok(data.clone()));
+        assert_eq!(zero_run_decode(&encoded, data.len() - 1), Err(()));
+        let bomb = [0u8, 255].repeat(1024);
+        assert_eq!(zero_run_decode(&bomb, 255 * 1023), Err(()));
+    }
+
+    #[test]
+    fn test_legacy_bytes() {
+        // stored before flags were introduced
+        let full = sparse_proof(4);
+        let legacy = full.into_bytes();
+        assert_eq!(legacy[0], 1);
+        let decoded = MultiLayerProof::from_flagged_bytes(&legacy).unwrap();
+        assert_eq!(decoded.encode(), legacy);
+
+        let proof = sparse_proof(4).gen_proof(1).unwrap();
+        let legacy = proof.into_bytes();
+        assert_eq!(legacy[0], 0);
+        let decoded = MultiLayerProof::from_flagged_bytes(&legacy).unwrap();
+        assert_eq!(decoded.encode(), legacy);
+        assert!(decoded.contains(1, H256::repeat_byte(1)));
+    }
+
+    #[test]
+    fn test_flagged_bytes() {
+        let proof = sparse_proof(64);
+        let raw = proof.into_bytes();
+
+        let compressed = proof.into_flagged_bytes(true);
+        assert_eq!(compressed[0], PROOF_FLAG_ZERO_RUN);
+        assert!(compressed.len() + 1024 < raw.len());
+        let decoded = MultiLayerProof::from_flagged_bytes(&compressed).unwrap();
+        assert_eq!(decoded.encode(), raw);
+
+        let uncompressed = proof.into_flagged_bytes(false);
+        assert_eq!(uncompressed[0], PROOF_FLAG_RAW);
+        assert_eq!(&uncompressed[1..], &raw[..]);
+        let decoded = MultiLayerProof::from_flagged_bytes(&uncompressed).unwrap();
+        assert_eq!(decoded.encode(), raw);
+
+        assert!(MultiLayerProof::from_flagged_bytes(&[]).is_err());
+        assert!(MultiLayerProof::from_flagged_bytes(&[2]).is_err());
+    }
+}
@@ -1,15 +1,84 @@
-use sp_std::vec::Vec;
-use sp_runtime::{traits::Zero, generic::Era};
+use sp_std::{convert::TryFrom, vec::Vec};
+use sp_runtime::{traits::{Verify, Zero}, generic::Era};
 use codec::{Encode, Decode, Compact, Input};
-use sp_core::{Blake2Hasher, Hasher};
+use sp_core::{Blake2Hasher, Hasher, blake2_256, sr25519};
+
+/// Call index (module, function) of balances transfer wrapped by a V1 relay
+pub const TRANSFER_CALL: (u8, u8) = (3, 0);
+
+/// Call index (module, function) of balances transfer wrapped by a V4 relay
+///
+/// MUST MATCH construct_runtime MACRO ORDER of the runtime
+pub const TRANSFER_CALL_V4: (u8, u8) = (4, 0);
+
+/// Call index (module, function) of relay transfer
+pub const RELAY_TRANSFER_CALL: (u8, u8) = (6, 0);
+
+/// Extrinsic format version of the legacy chain
+pub const EXTRINSIC_VERSION_V1: u8 = 1;
+
+/// Extrinsic format version of current Substrate
+pub const EXTRINSIC_VERSION_V4: u8 = 4;
+
+/// Signing payloads longer than this are signed by their blake2_256 hash
+const MAX_UNHASHED_PAYLOAD_LEN: usize = 256;
+
+/// MultiSignature variant of sr25519
+const SR25519_SIGNATURE: u8 = 1;
+
+/// Reason a relay payload fails to decode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelayDecodeError {
+    /// payload is truncated, or shorter than its length prefix claims
+    TooShort,
+    /// extrinsic format version is not supported
+    BadVersion(u8),
+    /// relay transfer is signed, it MUST be unsigned
+    UnexpectedSigned,
+    /// a field fails to decode
+    Codec(codec::Error),
+    /// signature is of unknown type or length
+    BadSignatureLen,
+    /// payload calls another module or function than expected
+    WrongCall { module: u8, func: u8 },
+}
+
+impl From<codec::Error> for RelayDecodeError {
+    fn from(e: codec::Error) -> Self {
+        RelayDecodeError::Codec(e)
+    }
+}
+
+/// Account reference as encoded in a transfer, selected by the leading type byte in V1,
+/// always the account id in V4
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelayAddress<AccountId> {
+    /// `0xff` followed by the full account id
+    Id(AccountId),
+    /// account index, inline when below `0xf0` or following `0xfc` (u16) / `0xfd` (u32)
+    Index(u32),
+}
+
+impl<AccountId> RelayAddress<AccountId> {
+    pub fn account_id(&self) -> Option<&AccountId> {
+        match self {
+            RelayAddress::Id(id) => Some(id),
+            RelayAddress::Index(_) => None,
+        }
+    }
+}
 
 pub struct OriginTransfer<Address, Balance> {
-    pub sender: Address,
+    pub sender: RelayAddress<Address>,
     pub signature: Vec<u8>,
     pub index: Compact<u64>,
     pub era: Era,
-    pub dest: Address,
+    pub dest: RelayAddress<Address>,
     pub amount: Balance,
+    version: u8,
+    signature_type: u8,
+    tip: Compact<u128>,
+    call: Vec<u8>,
 }
 
 pub struct RelayTransfer<Address, Balance, Hash> {
@@ -24,199 +93,186 @@ pub struct RelayTransfer<Address, Balance, Hash> {
 impl<Address, Balance> OriginTransfer<Address, Balance>
     where
         Address: Decode + Default + Clone,
-        Balance: Decode + Zero + Clone
+        Balance: TryFrom<u128> + Zero + Clone
 {
-    pub fn decode(data: &[u8]) -> Option<Self> {
+    pub fn decode(data: &[u8]) -> Result<Self, RelayDecodeError> {
         let mut input = data;
         if input.len() < 64 + 1 + 1 {
-            return None;
+            return Err(RelayDecodeError::TooShort);
         }
-        // length
-        let _len: Vec<()> = match Decode::decode(&mut input) {
-            Ok(len) => len,
-            Err(_) => return None
-        };
+        // length, MUST cover exactly the remaining bytes
+        decode_len_prefix(&mut input)?;
         // version
-        let version = match input.read_byte() {
-            Ok(v) => v,
-            Err(_) => return None
-        };
+        let version = input.read_byte()?;
         // is signed
         let is_signed = version & 0b1000_0000 != 0;
         let version = version & 0b0111_1111;
-        if version != 1u8 {
-            return None;
-        }
 
-        let (sender, signature, index, era) = if is_signed {
-            // sender type
-            let _type = match input.read_byte() {
-                Ok(a_t) => a_t,
-                Err(_) => return None
-            };
-            // sender
-            let sender = match Decode::decode(&mut input) {
-                Ok(s) => s,
-                Err(_) => return None
-            };
-            if input.len() < 64 {
-                return None;
-            }
-            // signature
-            let signature = input[..64].to_vec();
-            input = &input[64..];
-            // index
-            let index = match Decode::decode(&mut input) {
-                Ok(i) => i,
-                Err(_) => return None
-            };
-            if input.len() < 1 {
-                return None;
-            }
-            // era
-            let era = if input[0] != 0u8 {
-                match Decode::decode(&mut input) {
-                    Ok(e) => e,
-                    Err(_) => return None
-                }
-            } else {
-                input = &input[1..];
-                Era::Immortal
-            };
-            (sender, signature, index, era)
-        } else {
-            (Address::default(), Vec::new(), Compact(0u64), Era::Immortal)
+        let (sender, (signature_type, signature), index, era, tip) = match (is_signed, version) {
+            (true, EXTRINSIC_VERSION_V1) => decode_signature_v1(&mut input)?,
+            (true, EXTRINSIC_VERSION_V4) => decode_signature_v4(&mut input)?,
+            (false, EXTRINSIC_VERSION_V1) | (false, EXTRINSIC_VERSION_V4) =>
+                (RelayAddress::Id(Address::default()), (SR25519_SIGNATURE, Vec::new()), Compact(0u64), Era::Immortal, Compact(0u128)),
+            _ => return Err(RelayDecodeError::BadVersion(version)),
         };
 
-        if input.len() < 2 + 32 + 1 {
-            return None;
+        if input.len() < 2 + 1 + 1 {
+            return Err(RelayDecodeError::TooShort);
         }
+        // call, up to the end of the payload
+        let call = input.to_vec();
         // module
-        let _module: u8 = match input.read_byte() {
-            Ok(m) => m,
-            Err(_) => return None
-        };
+        let module: u8 = input.read_byte()?;
         // function
-        let _func: u8 = match input.read_byte() {
-            Ok(f) => f,
-            Err(_) => return None
-        };
-        // dest address type
-        let _type: u8 = match input.read_byte() {
-            Ok(t) => t,
-            Err(_) => return None
+        let func: u8 = input.read_byte()?;
+        let transfer_call = match version {
+            EXTRINSIC_VERSION_V1 => TRANSFER_CALL,
+            _ => TRANSFER_CALL_V4,
         };
+        if (module, func) != transfer_call {
+            return Err(RelayDecodeError::WrongCall { module, func });
+        }
         // dest address
-        let dest: Address = match Decode::decode(&mut input) {
-            Ok(addr) => addr,
-            Err(_) => return None
-        };
-        // amount
-        let amount: Balance = match Decode::decode(&mut input) {
-            Ok(a) => {
-                let a_c: Compact<u128> = a;
-                let buf = a_c.0.encode();
-                match Decode::decode(&mut buf.as_slice()) {
-                    Ok(am) => am,
-                    Err(_) => return None
-                }
-            }
-            Err(_) => return None
+        let dest = match version {
+            EXTRINSIC_VERSION_V1 => decode_address(&mut input)?,
+            _ => RelayAddress::Id(Decode::decode(&mut input)?),
         };
-        Some(OriginTransfer {
+        // amount, rejected if it does not fit into `Balance`
+        let amount: Compact<u128> = Decode::decode(&mut input)?;
+        let amount = Balance::try_from(amount.0)
+            .map_err(|_| codec::Error::from("Amount does not fit into balance"))?;
+        Ok(OriginTransfer {
             sender,
             signature,
             index,
             era,
             dest,
             amount,
+            version,
+            signature_type,
+            tip,
+            call,
         })
     }
 
-    pub fn sender(&self) -> Address {
+    /// Verify the sr25519 signature of the sender over the signing payload.
+    ///
+    /// Only immortal transfers can be checked, a mortal one is signed over
+    /// the hash of its birth block which is unknown here.
+    pub fn verify_signature(&self, genesis_hash: &[u8], spec_version: u32) -> bool
+        where Address: Encode
+    {
+        if self.signature_type != SR25519_SIGNATURE || self.era != Era::Immortal {
+            return false;
+        }
+        let sender = match self.sender.account_id() {
+            Some(id) => id,
+            None => return false,
+        };
+        let public = match sr25519::Public::decode(&mut sender.encode().as_slice()) {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+        let signature = match sr25519::Signature::decode(&mut self.signature.as_slice()) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+
+        let mut payload = Vec::new();
+        match self.version {
+            EXTRINSIC_VERSION_V1 => {
+                // (index, call, era, checkpoint)
+                self.index.encode_to(&mut payload);
+                payload.extend_from_slice(&self.call);
+                self.era.encode_to(&mut payload);
+                payload.extend_from_slice(genesis_hash);
+            }
+            EXTRINSIC_VERSION_V4 => {
+                // call, extra (era, nonce, tip), additional (spec version, genesis, era block)
+                payload.extend_from_slice(&self.call);
+                self.era.encode_to(&mut payload);
+                self.index.encode_to(&mut payload);
+                self.tip.encode_to(&mut payload);
+                spec_version.encode_to(&mut payload);
+                payload.extend_from_slice(genesis_hash);
+                payload.extend_from_slice(genesis_hash);
+            }
+            _ => return false,
+        }
+        if payload.len() > MAX_UNHASHED_PAYLOAD_LEN {
+            signature.verify(&blake2_256(&payload)[..], &public)
+        } else {
+            signature.verify(&payload[..], &public)
+        }
+    }
+
+    pub fn sender(&self) -> RelayAddress<Address> {
         self.sender.clone()
     }
 
-    pub fn dest(&self) -> Address {
+    pub fn dest(&self) -> RelayAddress<Address> {
         self.dest.clone()
     }
 
     pub fn amount(&self) -> Balance {
         self.amount.clone()
     }
+
+    /// Tip paid to the block author, V1 transfers carry no tip
+    pub fn tip(&self) -> u128 {
+        self.tip.0
+    }
 }
 
 impl<Address, Balance, Hash> RelayTransfer<Address, Balance, Hash>
     where
         Address: Decode + Default + Clone,
-        Balance: Decode + Zero + Clone,
+        Balance: TryFrom<u128> + Zero + Clone,
         Hash: Decode + Clone + Default,
 {
-    pub fn decode(data: &[u8]) -> Option<Self> {
+    pub fn decode(data: &[u8]) -> Result<Self, RelayDecodeError> {
         let mut input = data;
-        // length
-        let _len: Vec<()> = match Decode::decode(&mut input) {
-            Ok(len) => len,
-            Err(_) => return None
-        };
+        // length, MUST cover exactly the remaining bytes
+        decode_len_prefix(&mut input)?;
         // version
-        let version = match input.read_byte() {
-            Ok(v) => v,
-            Err(_) => return None
-        };
+        let version = input.read_byte()?;
         // is signed
         let is_signed = version & 0b1000_0000 != 0;
         let version = version & 0b0111_1111;
-        // has signed or version not satisfy
-        if is_signed || version != 1u8 {
-            return None;
+        if version != EXTRINSIC_VERSION_V1 && version != EXTRINSIC_VERSION_V4 {
+            return Err(RelayDecodeError::BadVersion(version));
+        }
+        if is_signed {
+            return Err(RelayDecodeError::UnexpectedSigned);
         }
         // module
-        let _module: u8 = match input.read_byte() {
-            Ok(m) => m,
-            Err(_) => return None
-        };
+        let module: u8 = input.read_byte()?;
         // function
-        let _func: u8 = match input.read_byte() {
-            Ok(f) => f,
-            Err(_) => return None
-        };
+        let func: u8 = input.read_byte()?;
+        if (module, func) != RELAY_TRANSFER_CALL {
+            return Err(RelayDecodeError::WrongCall { module, func });
+        }
         if input.len() < 64 + 32 + 32 + 2 {   // origin transfer min length
-            return None;
+            return Err(RelayDecodeError::TooShort);
         }
         // origin transfer
-        let origin: Vec<u8> = match Decode::decode(&mut input) {
-            Ok(ot) => ot,
-            Err(_) => return None
-        };
+        let origin: Vec<u8> = Decode::decode(&mut input)?;
         // which block's number the origin transfer in
-        let number: Compact<u64> = match Decode::decode(&mut input) {
-            Ok(h) => h,
-            Err(_) => return None
-        };
+        let number: Compact<u64> = Decode::decode(&mut input)?;
         // block hash
-        let block_hash: Hash = match Decode::decode(&mut input) {
-            Ok(h) => h,
-            Err(_) => return None
-        };
+        let block_hash: Hash = Decode::decode(&mut input)?;
         // which block's parent hash the origin transfer in
-        let parent: Hash = match Decode::decode(&mut input) {
-            Ok(h) => h,
-            Err(_) => return None
-        };
+        let parent: Hash = Decode::decode(&mut input)?;
         // decode origin transfer and build relay transfer
-        if let Some(ot) = OriginTransfer::decode(origin.clone().as_slice()) {
-            return Some(RelayTransfer {
-                transfer: ot,
-                number,
-                hash: Decode::decode(&mut Blake2Hasher::hash(origin.as_slice()).encode().as_slice()).unwrap(),
-                block_hash,
-                parent,
-                origin,
-            });
-        }
-
-        None
+        let ot = OriginTransfer::decode(origin.clone().as_slice())?;
+        Ok(RelayTransfer {
+            transfer: ot,
+            number,
+            hash: Decode::decode(&mut Blake2Hasher::hash(origin.as_slice()).encode().as_slice()).unwrap(),
+            block_hash,
+            parent,
+            origin,
+        })
     }
 
     pub fn number(&self) -> u64 {
@@ -235,7 +291,7 @@ impl<Address, Balance, Hash> RelayTransfer<Address, Balance, Hash>
         self.parent.clone()
     }
 
-    pub fn sender(&self) -> Address {
+    pub fn sender(&self) -> RelayAddress<Address> {
         self.transfer.sender()
     }
 
@@ -244,14 +300,404 @@ impl<Address, Balance, Hash> RelayTransfer<Address, Balance, Hash>
     }
 }
 
+/// Decode V1 signature part: sender, signature, index, era
+fn decode_signature_v1<Address: Decode>(input: &mut &[u8])
+    -> Result<(RelayAddress<Address>, (u8, Vec<u8>), Compact<u64>, Era, Compact<u128>), RelayDecodeError>
+{
+    // sender
+    let sender = decode_address(input)?;
+    if input.len() < 64 {
+        return Err(RelayDecodeError::TooShort);
+    }
+    // signature
+    let signature = input[..64].to_vec();
+    *input = &input[64..];
+    // index
+    let index = Decode::decode(input)?;
+    // era
+    let era = decode_era(input)?;
+    Ok((sender, (SR25519_SIGNATURE, signature), index, era, Compact(0u128)))
+}
+
+/// Decode V4 signature part: sender, multi signature, then signed extra era, nonce and tip
+fn decode_signature_v4<Address: Decode>(input: &mut &[u8])
+    -> Result<(RelayAddress<Address>, (u8, Vec<u8>), Compact<u64>, Era, Compact<u128>), RelayDecodeError>
+{
+    // sender, the account id itself as the runtime looks accounts up by IdentityLookup
+    let sender = RelayAddress::Id(Decode::decode(input)?);
+    // signature, led by the MultiSignature variant
+    let signature_type = input.read_byte()?;
+    let signature_len = match signature_type {
+        0 | 1 => 64,
+        2 => 65,
+        _ => return Err(RelayDecodeError::BadSignatureLen),
+    };
+    if input.len() < signature_len {
+        return Err(RelayDecodeError::TooShort);
+    }
+    let signature = input[..signature_len].to_vec();
+    *input = &input[signature_len..];
+    // era
+    let era = decode_era(input)?;
+    // nonce
+    let index = Decode::decode(input)?;
+    // tip
+    let tip = Decode::decode(input)?;
+    Ok((sender, (signature_type, signature), index, era, tip))
+}
+
+/// Decode era, a single zero byte is immortal
+fn decode_era(input: &mut &[u8]) -> Result<Era, RelayDecodeError> {
+    if input.len() < 1 {
+        return Err(RelayDecodeError::TooShort);
+    }
+    if input[0] != 0u8 {
+        Ok(Decode::decode(input)?)
+    } else {
+        *input = &input[1..];
+        Ok(Era::Immortal)
+    }
+}
+
+/// Decode the compact length prefix and check it against the remaining bytes
+fn decode_len_prefix(input: &mut &[u8]) -> Result<(), RelayDecodeError> {
+    let len = <Compact<u32>>::decode(input)?.0 as usize;
+    if len > input.len() {
+        return Err(RelayDecodeError::TooShort);
+    }
+    if len < input.len() {
+        return Err(codec::Error::from("Trailing bytes after payload").into());
+    }
+    Ok(())
+}
+
+/// Decode a V1 address prefixed by its type byte
+fn decode_address<AccountId: Decode>(input: &mut &[u8]) -> Result<RelayAddress<AccountId>, RelayDecodeError> {
+    let address_type = input.read_byte()?;
+    match address_type {
+        0xff => Ok(RelayAddress::Id(Decode::decode(input)?)),
+        0xfd => Ok(RelayAddress::Index(u32::decode(input)?)),
+        0xfc => Ok(RelayAddress::Index(u16::decode(input)? as u32)),
+        x if x < 0xf0 => Ok(RelayAddress::Index(x as u32)),
+        _ => Err(codec::Error::from("Unsupported address type").into()),
+    }
+}
+
+#[cfg(test)]
+fn with_len_prefix(body: &[u8]) -> Vec<u8> {
+    let mut data = Compact(body.len() as u32).encode();
+    data.extend_from_slice(body);
+    data
+}
+
 #[test]
 fn test_decode() {
     let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
     let data = hex::decode(tx).unwrap();
-    let ot = OriginTransfer::decode(data).unwrap();
-    let mut ot_a = ot.amount;
-    let amount: u128 = Decode::decode(&mut ot_a).unwrap().into();
+    let ot: OriginTransfer<[u8; 32], u128> = OriginTransfer::decode(data.as_slice()).unwrap();
 
-    assert_eq!(amount, 1000u128);
+    assert_eq!(ot.amount(), 1000u128);
     //assert_eq!(ot.index, 1);
 }
+
+#[test]
+fn test_decode_amount_width() {
+    let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
+    let data = hex::decode(tx).unwrap();
+    let ot: OriginTransfer<[u8; 32], u64> = OriginTransfer::decode(data.as_slice()).unwrap();
+    assert_eq!(ot.amount(), 1000u64);
+
+    // same transfer carrying an amount above u64::MAX
+    let big = u64::max_value() as u128 + 1;
+    let mut body = data[2..data.len() - 2].to_vec();
+    body.extend(Compact(big).encode());
+    let data = with_len_prefix(&body);
+
+    let ot: OriginTransfer<[u8; 32], u128> = OriginTransfer::decode(data.as_slice()).unwrap();
+    assert_eq!(ot.amount(), big);
+    let ot: Option<OriginTransfer<[u8; 32], u64>> = OriginTransfer::decode(data.as_slice()).ok();
+    assert!(ot.is_none());
+}
+
+#[test]
+fn test_decode_address_type() {
+    let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
+    let data = hex::decode(tx).unwrap();
+
+    // AccountId
+    let ot: OriginTransfer<[u8; 32], u128> = OriginTransfer::decode(data.as_slice()).unwrap();
+    let sender = hex::decode("784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338").unwrap();
+    assert_eq!(ot.sender().account_id().map(|a| a.to_vec()), Some(sender));
+    let dest = hex::decode("8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48").unwrap();
+    assert_eq!(ot.dest().account_id().map(|a| a.to_vec()), Some(dest));
+
+    // dest replaced by account indices, transfer head is kept up to the dest type byte
+    let head = &data[2..data.len() - 2 - 33];
+    let amount = &data[data.len() - 2..];
+    let cases: Vec<(Vec<u8>, Option<RelayAddress<[u8; 32]>>)> = vec![
+        (vec![0x2a], Some(RelayAddress::Index(0x2a))),
+        (vec![0xfc, 0x34, 0x12], Some(RelayAddress::Index(0x1234))),
+        (vec![0xfd, 0x78, 0x56, 0x34, 0x12], Some(RelayAddress::Index(0x12345678))),
+        (vec![0xfe, 0, 0, 0, 0, 1, 0, 0, 0], None),
+    ];
+    for (dest, expected) in cases {
+        let mut body = head.to_vec();
+        body.extend(dest);
+        body.extend_from_slice(amount);
+        let data = with_len_prefix(&body);
+        let ot: Option<OriginTransfer<[u8; 32], u128>> = OriginTransfer::decode(data.as_slice()).ok();
+        assert_eq!(ot.map(|ot| ot.dest()), expected);
+    }
+}
+
+#[test]
+fn test_decode_len_prefix() {
+    let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
+    let data = hex::decode(tx).unwrap();
+    let body = &data[2..];
+
+    let ot: Option<OriginTransfer<[u8; 32], u128>> = OriginTransfer::decode(data.as_slice()).ok();
+    assert!(ot.is_some());
+
+    // prefix claims more bytes than present
+    let mut lying = Compact(body.len() as u32 + 100).encode();
+    lying.extend_from_slice(body);
+    let ot: Option<OriginTransfer<[u8; 32], u128>> = OriginTransfer::decode(lying.as_slice()).ok();
+    assert!(ot.is_none());
+
+    // truncated payload keeping the original prefix
+    let truncated = &data[..data.len() - 1];
+    let ot: Option<OriginTransfer<[u8; 32], u128>> = OriginTransfer::decode(truncated).ok();
+    assert!(ot.is_none());
+
+    // relay transfer wrapping the origin transfer
+    let mut relay = vec![0x01, 0x06, 0x00];
+    relay.extend(data.encode());
+    relay.extend(Compact(10u64).encode());
+    relay.extend_from_slice(&[1u8; 32]);
+    relay.extend_from_slice(&[2u8; 32]);
+    let relay_data = with_len_prefix(&relay);
+    let rt: RelayTransfer<[u8; 32], u128, [u8; 32]> = RelayTransfer::decode(relay_data.as_slice()).unwrap();
+    assert_eq!(rt.number(), 10);
+    assert_eq!(rt.block_hash(), [1u8; 32]);
+    assert_eq!(rt.parent(), [2u8; 32]);
+
+    let mut lying = Compact(relay.len() as u32 + 1).encode();
+    lying.extend_from_slice(&relay);
+    let rt: Option<RelayTransfer<[u8; 32], u128, [u8; 32]>> = RelayTransfer::decode(lying.as_slice()).ok();
+    assert!(rt.is_none());
+}
+
+#[test]
+fn test_decode_call() {
+    let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
+    let data = hex::decode(tx).unwrap();
+    assert!(OriginTransfer::<[u8; 32], u128>::decode(data.as_slice()).is_ok());
+
+    // module and function bytes follow the era byte
+    let call_at = data.len() - 2 - 33 - 2;
+    assert_eq!(&data[call_at..call_at + 2], &[0x03, 0x00]);
+    let mut wrong = data.clone();
+    wrong[call_at] = 0x04;
+    wrong[call_at + 1] = 0x01;
+    assert_eq!(
+        OriginTransfer::<[u8; 32], u128>::decode(wrong.as_slice()).err(),
+        Some(RelayDecodeError::WrongCall { module: 0x04, func: 0x01 })
+    );
+
+    let relay_body = |call: [u8; 2]| {
+        let mut relay = vec![0x01, call[0], call[1]];
+        relay.extend(data.encode());
+        relay.extend(Compact(10u64).encode());
+        relay.extend_from_slice(&[1u8; 32]);
+        relay.extend_from_slice(&[2u8; 32]);
+        with_len_prefix(&relay)
+    };
+    assert!(RelayTransfer::<[u8; 32], u128, [u8; 32]>::decode(&relay_body([0x06, 0x00])).is_ok());
+    assert_eq!(
+        RelayTransfer::<[u8; 32], u128, [u8; 32]>::decode(&relay_body([0x03, 0x00])).err(),
+        Some(RelayDecodeError::WrongCall { module: 0x03, func: 0x00 })
+    );
+}
+
+#[test]
+fn test_decode_v4() {
+    use node_runtime::{AccountId, BalancesCall, Call, Runtime, SignedExtra, UncheckedExtrinsic, VERSION};
+    use sp_core::{H256, Pair};
+    use sp_runtime::{generic::SignedPayload, MultiSignature};
+
+    // transfer signed and encoded by the runtime
+    let pair = sr25519::Pair::from_seed(&[1u8; 32]);
+    let sender = AccountId::from(pair.public().0);
+    let dest = AccountId::from([2u8; 32]);
+    let genesis_hash = H256::repeat_byte(3);
+    let call = Call::Balances(BalancesCall::transfer(dest.clone(), 1000));
+    let extra: SignedExtra = (
+        frame_system::CheckVersion::<Runtime>::new(),
+        frame_system::CheckGenesis::<Runtime>::new(),
+        frame_system::CheckEra::<Runtime>::from(Era::Immortal),
+        frame_system::CheckNonce::<Runtime>::from(7),
+        frame_system::CheckWeight::<Runtime>::new(),
+        pallet_transaction_payment::ChargeTransactionPayment::<Runtime>::from(0),
+    );
+    let payload = SignedPayload::from_raw(
+        call.clone(),
+        extra.clone(),
+        (VERSION.spec_version, genesis_hash, genesis_hash, (), (), ()),
+    );
+    let signature = payload.using_encoded(|payload| pair.sign(payload));
+    let v4_data = UncheckedExtrinsic::new_signed(call, sender.clone(), MultiSignature::from(signature), extra).encode();
+
+    let v4: OriginTransfer<AccountId, u128> = OriginTransfer::decode(v4_data.as_slice()).unwrap();
+    assert_eq!(v4.sender(), RelayAddress::Id(sender));
+    assert_eq!(v4.index, Compact(7u64));
+    assert_eq!(v4.era, Era::Immortal);
+    assert_eq!(v4.dest(), RelayAddress::Id(dest));
+    assert_eq!(v4.amount(), 1000u128);
+    assert_eq!(v4.tip(), 0);
+    assert!(v4.verify_signature(genesis_hash.as_ref(), VERSION.spec_version));
+
+    let mut input = v4_data.as_slice();
+    let _ = <Compact<u32>>::decode(&mut input).unwrap();
+    let body = input.to_vec();
+
+    // unknown multi signature variant, right after the 32 bytes sender
+    let mut bad = body.clone();
+    bad[33] = 0x03;
+    assert!(OriginTransfer::<AccountId, u128>::decode(&with_len_prefix(&bad)).is_err());
+
+    // unknown version
+    let mut bad = body.clone();
+    bad[0] = 0x85;
+    assert!(OriginTransfer::<AccountId, u128>::decode(&with_len_prefix(&bad)).is_err());
+
+    // V4 relay transfer wrapping the V4 origin transfer
+    let mut relay = vec![0x04, 0x06, 0x00];
+    relay.extend(v4_data.encode());
+    relay.extend(Compact(10u64).encode());
+    relay.extend_from_slice(&[1u8; 32]);
+    relay.extend_from_slice(&[2u8; 32]);
+    let rt: RelayTransfer<AccountId, u128, [u8; 32]> = RelayTransfer::decode(&with_len_prefix(&relay)).unwrap();
+    assert_eq!(rt.origin(), v4_data);
+    assert_eq!(rt.transfer.amount(), 1000u128);
+}
+
+#[test]
+fn test_decode_error() {
+    let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
+    let data = hex::decode(tx).unwrap();
+    let body = &data[2..];
+    let decode = |data: &[u8]| OriginTransfer::<[u8; 32], u128>::decode(data).err();
+
+    // too short
+    assert_eq!(decode(&data[..10]), Some(RelayDecodeError::TooShort));
+    assert_eq!(decode(&data[..data.len() - 1]), Some(RelayDecodeError::TooShort));
+
+    // bad version
+    let mut bad = body.to_vec();
+    bad[0] = 0x82;
+    assert_eq!(decode(&with_len_prefix(&bad)), Some(RelayDecodeError::BadVersion(2)));
+
+    // codec, unsupported dest address type
+    let mut bad = body.to_vec();
+    let dest_at = bad.len() - 2 - 33;
+    bad[dest_at] = 0xfe;
+    match decode(&with_len_prefix(&bad)) {
+        Some(RelayDecodeError::Codec(_)) => {}
+        e => panic!("expected codec error, got {:?}", e),
+    }
+
+    // bad signature length, unknown V4 multi signature variant after the unprefixed sender
+    let mut bad = body.to_vec();
+    bad[0] = 0x84;
+    bad.remove(1);
+    bad[33] = 0x03;
+    assert_eq!(decode(&with_len_prefix(&bad)), Some(RelayDecodeError::BadSignatureLen));
+
+    // signed relay transfer
+    let mut relay = vec![0x81, 0x06, 0x00];
+    relay.extend(data.encode());
+    relay.extend(Compact(10u64).encode());
+    relay.extend_from_slice(&[1u8; 32]);
+    relay.extend_from_slice(&[2u8; 32]);
+    assert_eq!(
+        RelayTransfer::<[u8; 32], u128, [u8; 32]>::decode(&with_len_prefix(&relay)).err(),
+        Some(RelayDecodeError::UnexpectedSigned)
+    );
+}
+
+#[test]
+fn test_verify_signature() {
+    use sp_core::Pair;
+
+    let pair = sr25519::Pair::from_seed(&[1u8; 32]);
+    let genesis_hash = [7u8; 32];
+    let spec_version = 3u32;
+
+    let mut call = vec![TRANSFER_CALL_V4.0, TRANSFER_CALL_V4.1];
+    call.extend_from_slice(&[9u8; 32]);
+    call.extend(Compact(1000u128).encode());
+
+    let mut payload = call.clone();
+    payload.push(0x00);
+    payload.extend(Compact(5u64).encode());
+    payload.extend(Compact(0u128).encode());
+    payload.extend(spec_version.encode());
+    payload.extend_from_slice(&genesis_hash);
+    payload.extend_from_slice(&genesis_hash);
+    let signature = pair.sign(&payload);
+
+    let signed = |call: &[u8]| {
+        let mut body = vec![0x84];
+        body.extend_from_slice(pair.public().as_ref());
+        body.push(SR25519_SIGNATURE);
+        body.extend_from_slice(signature.as_ref());
+        body.push(0x00);
+        body.extend(Compact(5u64).encode());
+        body.extend(Compact(0u128).encode());
+        body.extend_from_slice(call);
+        with_len_prefix(&body)
+    };
+
+    let ot = OriginTransfer::<[u8; 32], u128>::decode(&signed(&call)).unwrap();
+    assert!(ot.verify_signature(&genesis_hash, spec_version));
+    assert!(!ot.verify_signature(&genesis_hash, spec_version + 1));
+    assert!(!ot.verify_signature(&[8u8; 32], spec_version));
+
+    // amount tampered
+    let mut tampered = call.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0x04;
+    let ot = OriginTransfer::<[u8; 32], u128>::decode(&signed(&tampered)).unwrap();
+    assert!(!ot.verify_signature(&genesis_hash, spec_version));
+}
+
+#[test]
+fn test_decode_tip() {
+    let tx = "250281ff784cb29a605b557c11a3e22520387c4377ded1734f56900d7f04946a0b70f338bc9b0ff2ffa4b95d4479cbaccefc7bbe908430f5c5ec571a25c71ee005d5755b65b7768dff90479a09f0d545384e57f057707664e2fa250818877a1a5a971f0f30000300ff8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48a10f";
+    let v1_data = hex::decode(tx).unwrap();
+    let v1: OriginTransfer<[u8; 32], u128> = OriginTransfer::decode(v1_data.as_slice()).unwrap();
+    assert_eq!(v1.tip(), 0);
+
+    // V4 layout, the tip follows era and nonce, addresses carry no type byte
+    let v1_body = &v1_data[2..];
+    let with_tip = |tip: u128| {
+        let mut body = vec![0x84];
+        body.extend_from_slice(&v1_body[2..34]);
+        body.push(0x01);
+        body.extend_from_slice(&v1_body[34..98]);
+        body.push(0x00);
+        body.extend(Compact(7u64).encode());
+        body.extend(Compact(tip).encode());
+        body.extend_from_slice(&[TRANSFER_CALL_V4.0, TRANSFER_CALL_V4.1]);
+        body.extend_from_slice(&v1_body[v1_body.len() - 32 - 2..]);
+        with_len_prefix(&body)
+    };
+
+    for &tip in &[0u128, 1, 1_000_000_000_000u128] {
+        let ot: OriginTransfer<[u8; 32], u128> = OriginTransfer::decode(&with_tip(tip)).unwrap();
+        assert_eq!(ot.tip(), tip);
+        assert_eq!(ot.dest(), v1.dest());
+        assert_eq!(ot.amount(), 1000u128);
+    }
+}
@@ -53,6 +53,11 @@ use yp_consensus_pow::inherents::{INHERENT_IDENTIFIER, InherentType, PowInherent
 use yp_sharding::ShardingInfo;
 use yp_sharding::utils::shard_num_for;
 
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 type PositiveImbalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::PositiveImbalance;
 
@@ -154,10 +159,15 @@ decl_module! {
 
                 let new_reward_plan = RewardPlan{
                     block_number,
-                    coinbase: coinbase.clone(),
+                    coinbase,
                     block_reward,
                     fee_reward,
                 };
+                // reward of a slashed block is withheld, matured plans of other blocks are paid as usual
+                let withheld = match reward_condition {
+                    RewardCondition::Slash(_) => true,
+                    RewardCondition::Normal => false,
+                };
 
                 let reward_block_number = if block_number > Self::block_reward_latency() {
                     block_number - Self::block_reward_latency()
@@ -166,12 +176,14 @@ decl_module! {
                 };
 
                 <Self as Store>::RewardPlans::mutate(|orig| {
-                    orig.push(new_reward_plan.clone());
+                    if !withheld {
+                        orig.push(new_reward_plan.clone());
+                    }
 
                     orig.retain(|x|{
                         let reward = x.block_number <= reward_block_number;
                         if reward{
-                            Self::reward(x, coinbase.clone(), reward_condition.clone());
+                            Self::reward(x);
                         }
                         !reward
                     });
@@ -190,27 +202,18 @@ decl_event!(
 );
 
 impl<T: Trait> Module<T> {
-	fn reward(reward_plan: &RewardPlan<T::BlockNumber, T::AccountId, BalanceOf<T>>, current_coinbase: T::AccountId, reward_condition: RewardCondition) {
+	fn reward(reward_plan: &RewardPlan<T::BlockNumber, T::AccountId, BalanceOf<T>>) {
 		let shard_num: u16 = T::Sharding::get_curr_shard().expect("qed").try_into().ok().expect("qed") as u16;
 		let shard_count: u16 = T::Sharding::get_shard_count().try_into().ok().expect("qed") as u16;
 		let coinbase_shard_num = shard_num_for(&reward_plan.coinbase, shard_count).expect("qed");
 
 		//when scaling out, only one splitted shard will perform rewarding
 		if coinbase_shard_num == shard_num {
-			let reward_target = match reward_condition {
-				RewardCondition::Normal => &reward_plan.coinbase,
-				RewardCondition::Slash => &current_coinbase,
-			};
 			let reward_amount = reward_plan.block_reward + reward_plan.fee_reward;
-			let imbalance = T::Currency::deposit_creating(reward_target, reward_amount);
+			let imbalance = T::Currency::deposit_creating(&reward_plan.coinbase, reward_amount);
 			T::Reward::on_unbalanced(imbalance);
 
-			Self::deposit_event(RawEvent::Reward(RewardPlan {
-				block_number: reward_plan.block_number.clone(),
-				coinbase: reward_target.clone(),
-				block_reward: reward_plan.block_reward,
-				fee_reward: reward_plan.fee_reward,
-			}));
+			Self::deposit_event(RawEvent::Reward(reward_plan.clone()));
 		}
 	}
 }
@@ -226,7 +229,7 @@ impl<T: Trait> ProvideInherent for Module<T> {
 		Some(Call::set_pow_info(data))
 	}
 
-	fn check_inherent(_: &Self::Call, data: &InherentData) -> Result<(), Self::Error> {
+	fn check_inherent(call: &Self::Call, data: &InherentData) -> Result<(), Self::Error> {
 		let d = data.get_data(&INHERENT_IDENTIFIER)
 			.and_then(|r| r.ok_or_else(|| "YeePow inherent data not found".into()));
 		let d: InherentType<T::AccountId> = match d {
@@ -234,6 +237,14 @@ impl<T: Trait> ProvideInherent for Module<T> {
 			_ => return Ok(())
 		};
 
+		// a block the verifier found in violation is accepted only if it declares the slash
+		if let RewardCondition::Slash(_) = d.reward_condition {
+			match call {
+				Call::set_pow_info(info) if info.reward_condition == d.reward_condition => {}
+				_ => return Err(sp_inherents::Error::from("block not slashed").into()),
+			}
+		}
+
 		let shard = data.get_data(&yp_sharding::inherents::INHERENT_IDENTIFIER)
 			.and_then(|r| r.ok_or_else(|| "Sharding inherent data not found".into()));
 		let shard: yp_sharding::inherents::InherentType = match shard {
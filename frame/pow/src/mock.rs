@@ -0,0 +1,146 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Test utilities
+
+use frame_support::{impl_outer_event, impl_outer_origin, parameter_types, weights::Weight};
+use sp_core::H256;
+use sp_runtime::{
+	Perbill,
+	generic::DigestItem,
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+};
+use yp_consensus_pow::{PowInfo, RewardCondition};
+use yp_sharding::ShardingInfo;
+
+use crate::{GenesisConfig, Module, Trait};
+
+impl_outer_origin! {
+	pub enum Origin for Test where system = frame_system {}
+}
+
+mod pow {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Test {
+		frame_system<T>,
+		pallet_balances<T>,
+		pallet_sharding<T>,
+		pow<T>,
+	}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+	pub const ExistentialDeposit: u64 = 1;
+}
+
+impl frame_system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type ModuleToIndex = ();
+	type AccountData = pallet_balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+}
+
+impl pallet_balances::Trait for Test {
+	type Balance = u64;
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+}
+
+impl pallet_sharding::Trait for Test {
+	type ShardNum = u16;
+	type Log = DigestItem<H256>;
+	type Event = TestEvent;
+}
+
+/// Single shard chain, every coinbase is on shard 0
+pub struct SingleShard;
+
+impl ShardingInfo<u16> for SingleShard {
+	fn get_genesis_shard_count() -> u16 {
+		1
+	}
+
+	fn get_curr_shard() -> Option<u16> {
+		Some(0)
+	}
+
+	fn get_shard_count() -> u16 {
+		1
+	}
+}
+
+impl Trait for Test {
+	type PowTarget = u64;
+	type Currency = Balances;
+	type Reward = ();
+	type Event = TestEvent;
+	type Sharding = SingleShard;
+}
+
+pub type System = frame_system::Module<Test>;
+pub type Balances = pallet_balances::Module<Test>;
+pub type Pow = Module<Test>;
+
+/// Reward per block
+pub const BLOCK_REWARD: u64 = 100;
+
+pub fn new_test_ext(block_reward_latency: u64) -> sp_io::TestExternalities {
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisConfig::<Test> {
+		genesis_pow_target: 0,
+		pow_target_adj: 10,
+		target_block_time: 30,
+		block_reward: BLOCK_REWARD,
+		block_reward_latency,
+	}.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
+
+pub fn pow_info(coinbase: u64, reward_condition: RewardCondition) -> PowInfo<u64> {
+	PowInfo { coinbase, reward_condition }
+}
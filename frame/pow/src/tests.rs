@@ -0,0 +1,83 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for the module.
+
+use frame_support::{assert_ok, traits::{OnFinalize, OnInitialize}};
+use sp_inherents::{InherentData, ProvideInherent};
+use yp_consensus_pow::{RewardCondition, SlashReason};
+use yp_consensus_pow::inherents::INHERENT_IDENTIFIER;
+
+use crate::Call;
+use crate::mock::{Balances, BLOCK_REWARD, new_test_ext, Origin, Pow, pow_info, System};
+
+fn run_block(number: u64, coinbase: u64, reward_condition: RewardCondition) {
+	System::set_block_number(number);
+	Pow::on_initialize(number);
+	assert_ok!(Pow::set_pow_info(Origin::none(), pow_info(coinbase, reward_condition)));
+	Pow::on_finalize(number);
+}
+
+fn slash() -> RewardCondition {
+	RewardCondition::Slash(SlashReason::InvalidRelayProof)
+}
+
+#[test]
+fn slashed_block_imports_without_reward() {
+	new_test_ext(2).execute_with(|| {
+		run_block(1, 1, slash());
+		assert!(Pow::reward_plans().is_empty());
+
+		for number in 2..5 {
+			run_block(number, 2, RewardCondition::Normal);
+		}
+		// block 1 matured at block 3 with nothing to pay
+		assert_eq!(Balances::free_balance(1), 0);
+		assert_eq!(Balances::free_balance(2), BLOCK_REWARD);
+		assert_eq!(Pow::reward_plans().iter().map(|x| x.block_number).collect::<Vec<_>>(), vec![3, 4]);
+	});
+}
+
+#[test]
+fn slashed_block_does_not_take_matured_rewards() {
+	new_test_ext(2).execute_with(|| {
+		run_block(1, 1, RewardCondition::Normal);
+		run_block(2, 1, RewardCondition::Normal);
+		// plan of block 1 matures in a slashed block of another miner
+		run_block(3, 2, slash());
+
+		assert_eq!(Balances::free_balance(1), BLOCK_REWARD);
+		assert_eq!(Balances::free_balance(2), 0);
+	});
+}
+
+#[test]
+fn slash_must_be_declared_by_block() {
+	let data = |reward_condition: RewardCondition| {
+		let mut data = InherentData::new();
+		data.put_data(INHERENT_IDENTIFIER, &pow_info(1, reward_condition)).unwrap();
+		data
+	};
+	let call = |reward_condition: RewardCondition| Call::set_pow_info(pow_info(1, reward_condition));
+
+	new_test_ext(2).execute_with(|| {
+		assert!(Pow::check_inherent(&call(RewardCondition::Normal), &data(RewardCondition::Normal)).is_ok());
+		assert!(Pow::check_inherent(&call(slash()), &data(slash())).is_ok());
+		assert!(Pow::check_inherent(&call(RewardCondition::Normal), &data(slash())).is_err());
+		assert!(Pow::check_inherent(&call(RewardCondition::Slash(SlashReason::ForeignFork)), &data(slash())).is_err());
+	});
+}
@@ -25,11 +25,12 @@ use {
 };
 use {
 	frame_support::{
-		decl_module, decl_storage,
+		debug, decl_event, decl_module, decl_storage, ensure,
 		storage::StorageValue,
+		weights::Weight,
 	},
-	frame_system::{self as system, ensure_none},
-	sp_arithmetic::traits::BaseArithmetic,
+	frame_system::{self as system, ensure_none, ensure_root},
+	sp_arithmetic::traits::{BaseArithmetic, CheckedAdd, One, Zero},
 	sp_inherents::{
 		InherentData, InherentIdentifier,
 		MakeFatalError, ProvideInherent,
@@ -41,6 +42,7 @@ use {
 		traits::{
 			MaybeDisplay,
 			MaybeSerializeDeserialize, Member,
+			Saturating, UniqueSaturatedInto,
 		},
 	},
 	sp_std::fmt::Debug,
@@ -48,6 +50,14 @@ use {
 	yp_sharding::inherents::INHERENT_IDENTIFIER
 };
 
+pub use yp_sharding::ScaleOutPhase;
+
+mod migration;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
 pub type Log<T> = RawLog<<T as Trait>::ShardNum, <T as system::Trait>::BlockNumber>;
 
 /// Logs in this module.
@@ -57,6 +67,7 @@ pub enum RawLog<ShardNum, BlockNumber> {
 	/// Block Header digest log for shard info
 	ShardMarker(ShardNum, ShardNum),
 	ScaleOutPhase(ScaleOutPhase<BlockNumber, ShardNum>),
+	ScaleInPhase(ScaleInPhase<BlockNumber, ShardNum>),
 }
 
 pub trait Trait: system::Trait {
@@ -64,8 +75,30 @@ pub trait Trait: system::Trait {
 	type ShardNum: Parameter + Member + MaybeSerializeDeserialize + Debug + Default + Copy + MaybeDisplay + BaseArithmetic + Codec;
 	/// Type for all log entries of this module.
 	type Log: From<Log<Self>> + Into<system::DigestItemOf<Self>>;
+	/// Type for events of this module.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 }
 
+decl_event!(
+	pub enum Event<T> where
+		<T as system::Trait>::BlockNumber,
+		<T as Trait>::ShardNum,
+	{
+		/// Scale out to shard started, observed until block
+		ScaleOutStarted(ShardNum, BlockNumber),
+		/// Scale out to shard is ready on native shard, observed until block
+		ScaleOutNativeReady(ShardNum, BlockNumber),
+		/// Scale out to shard is ready, observed until block
+		ScaleOutReady(ShardNum, BlockNumber),
+		/// Scale out is committing to shard count
+		ScaleOutCommitting(ShardNum),
+		/// Scale out committed to shard num and shard count
+		ScaleOutCommitted(ShardNum, ShardNum),
+		/// Scale out observe blocks changed
+		ScaleOutObserveBlocksChanged(BlockNumber),
+	}
+);
+
 /*
 #[cfg(any(feature = "std", test))]
 impl<N> From<RawLog<N>> for runtime_primitives::testing::DigestItem {
@@ -79,31 +112,43 @@ impl<N> From<RawLog<N>> for runtime_primitives::testing::DigestItem {
 }
 */
 
+/// Phase of merging shard `source` into shard `dest`
 #[derive(Clone, PartialEq, Eq)]
 #[derive(Decode, Encode)]
 #[cfg_attr(feature = "std", derive(Debug, Serialize))]
-pub enum ScaleOutPhase<BlockNumber, ShardNum> {
+pub enum ScaleInPhase<BlockNumber, ShardNum> {
 	Started {
 		observe_util: BlockNumber,
-		shard_num: ShardNum,
-	},
-	NativeReady {
-		observe_util: BlockNumber,
-		shard_num: ShardNum,
+		source: ShardNum,
+		dest: ShardNum,
 	},
 	Ready {
 		observe_util: BlockNumber,
-		shard_num: ShardNum,
-	},
-	Commiting {
-		shard_count: ShardNum,
+		source: ShardNum,
+		dest: ShardNum,
 	},
 	Committed {
-		shard_num: ShardNum,
-		shard_count: ShardNum,
+		source: ShardNum,
+		dest: ShardNum,
 	},
 }
 
+/// Storage layout version of this module
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Releases {
+	V0,
+	V1,
+	/// `ShardInfo` gained `scale_in`
+	V2,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V0
+	}
+}
+
 decl_storage! {
     trait Store for Module<T: Trait> as Sharding {
         /// Total sharding count used in genesis block
@@ -112,22 +157,79 @@ decl_storage! {
         /// Total sharding count used in genesis block
         pub ScaleOutObserveBlocks get(fn scale_out_observe_blocks) config(): T::BlockNumber;
 
+        /// Lower bound of ScaleOutObserveBlocks checked at genesis,
+        /// a too short window collapses scale out phases into back-to-back blocks
+        pub MinScaleOutObserveBlocks get(fn min_scale_out_observe_blocks) config(): T::BlockNumber = One::one();
+
+        /// Percentage 0-100 of blocks in observe window that MUST come from scaled out nodes
+        /// before scale out advances from Started
+        pub ScaleOutReadyThreshold get(fn scale_out_ready_threshold) config(): u8;
+
+        /// Blocks from scaled out nodes in current Started observe window
+        pub ScaleOutObservedBlocks get(fn scale_out_observed_blocks): T::BlockNumber;
+
+        /// Length of current Started observe window, taken when the window begins
+        /// so that changing ScaleOutObserveBlocks does not apply to blocks already observed
+        pub ScaleOutObservingBlocks get(fn scale_out_observing_blocks): Option<T::BlockNumber>;
+
         /// Storage for ShardInfo used for current block
         pub CurrentShardInfo get(fn current_shard_info): Option<ShardInfo<T::ShardNum>>;
 
         /// Storage for ScaleOutPhase used for current block
         pub CurrentScaleOutPhase get(fn current_scale_out_phase): Option<ScaleOutPhase<T::BlockNumber, T::ShardNum>>;
 
+        /// Storage for ScaleInPhase used for current block
+        pub CurrentScaleInPhase get(fn current_scale_in_phase): Option<ScaleInPhase<T::BlockNumber, T::ShardNum>>;
+
+        /// Storage layout version, chains started before versioning are V0
+        pub StorageVersion get(fn storage_version) build(|_| Releases::V2): Releases;
+
+    }
+    add_extra_genesis {
+        build(|config: &GenesisConfig<T>| {
+            // counts reachable by doubling genesis count MUST pass the power of two check of inherents
+            assert!(
+                Module::<T>::is_power_of_two(config.genesis_sharding_count),
+                "Genesis sharding count must be a power of two"
+            );
+            assert!(
+                config.min_scale_out_observe_blocks > Zero::zero(),
+                "Min scale out observe blocks must be positive"
+            );
+            assert!(
+                config.scale_out_observe_blocks >= config.min_scale_out_observe_blocks,
+                "Scale out observe blocks is less than min scale out observe blocks"
+            );
+        })
     }
 }
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 
+        fn deposit_event() = default;
+
         #[weight = 0]
         fn set_shard_info(origin, info: ShardInfo<T::ShardNum>) {
             ensure_none(origin)?;
 
+            // a mandatory inherent MUST NOT fail, otherwise shard info goes stale for the block
+            // and phases waiting on observe_util of the block never advance,
+            // invalid scale out or scale in is ignored and logged instead
+            let mut info = info;
+            if let Some(scale_out) = info.scale_out.as_ref() {
+                if let Err(e) = Self::check_scale_out(&info, scale_out.shard_num) {
+                    debug::warn!("Ignore scale out to {:?}: {}", scale_out.shard_num, e);
+                    info.scale_out = None;
+                }
+            }
+            if let Some(scale_in) = info.scale_in.as_ref() {
+                if let Err(e) = Self::check_scale_in(&info, scale_in.shard_num) {
+                    debug::warn!("Ignore scale in to {:?}: {}", scale_in.shard_num, e);
+                    info.scale_in = None;
+                }
+            }
+
             let info_clone = info.clone();
             <Self as Store>::CurrentShardInfo::mutate(|orig| {
                 *orig = Some(info_clone);
@@ -138,32 +240,75 @@ decl_module! {
 
             let current_scale_out_phase = Self::current_scale_out_phase();
 
-            let target_shard_num = match info.scale_out.clone(){
+            let requested_shard_num = match info.scale_out.clone(){
                 Some(scale_out) => scale_out.shard_num,
                 None => info.num,
             };
 
+            // an in-progress scale out keeps its target until the phase resolves
+            let target_shard_num = match Self::in_progress_scale_out_target() {
+                Some(in_progress) => {
+                    if info.scale_out.is_some() && requested_shard_num != in_progress {
+                        debug::warn!(
+                            "Ignore scale out to {:?} while scale out to {:?} is in progress",
+                            requested_shard_num, in_progress
+                        );
+                    }
+                    in_progress
+                },
+                None => requested_shard_num,
+            };
+
             match current_scale_out_phase {
                 None => {
                     if let Some(_) = info.scale_out {
+                        let observe_util = block_number + scale_out_observe_blocks;
                         <Self as Store>::CurrentScaleOutPhase::mutate(|orig| {
                             *orig = Some(ScaleOutPhase::Started{
-                                observe_util: block_number + scale_out_observe_blocks,
+                                observe_util,
                                 shard_num: target_shard_num,
                             });
                         });
+                        <Self as Store>::ScaleOutObservedBlocks::put(T::BlockNumber::one());
+                        <Self as Store>::ScaleOutObservingBlocks::put(scale_out_observe_blocks);
+                        Self::deposit_event(RawEvent::ScaleOutStarted(target_shard_num, observe_util));
                     }
                 },
                 Some(current_scale_out_phase) => match current_scale_out_phase{
                     ScaleOutPhase::Started{observe_util, ..} => {
 
-                        //TODO: check scaled shard_num percentage
                         if observe_util == block_number{
-                            <Self as Store>::CurrentScaleOutPhase::mutate(|orig| {
-                                *orig = Some(ScaleOutPhase::NativeReady{
-                                    observe_util: block_number + scale_out_observe_blocks,
-                                    shard_num: target_shard_num,
+                            let observe_util = block_number + scale_out_observe_blocks;
+                            if Self::is_scale_out_ready() {
+                                <Self as Store>::CurrentScaleOutPhase::mutate(|orig| {
+                                    *orig = Some(ScaleOutPhase::NativeReady{
+                                        observe_util,
+                                        shard_num: target_shard_num,
+                                    });
+                                });
+                                <Self as Store>::ScaleOutObservedBlocks::kill();
+                                <Self as Store>::ScaleOutObservingBlocks::kill();
+                                Self::deposit_event(RawEvent::ScaleOutNativeReady(target_shard_num, observe_util));
+                            } else {
+                                // not enough scaled out nodes yet, observe another window
+                                debug::warn!(
+                                    "Scale out to {:?} not ready, {:?} of {:?} blocks observed",
+                                    target_shard_num, Self::scale_out_observed_blocks(), scale_out_observe_blocks
+                                );
+                                <Self as Store>::CurrentScaleOutPhase::mutate(|orig| {
+                                    *orig = Some(ScaleOutPhase::Started{
+                                        observe_util,
+                                        shard_num: target_shard_num,
+                                    });
                                 });
+                                let observed = if info.scale_out.is_some() { One::one() } else { Zero::zero() };
+                                <Self as Store>::ScaleOutObservedBlocks::put(observed);
+                                <Self as Store>::ScaleOutObservingBlocks::put(scale_out_observe_blocks);
+                                Self::deposit_event(RawEvent::ScaleOutStarted(target_shard_num, observe_util));
+                            }
+                        } else if info.scale_out.is_some() {
+                            <Self as Store>::ScaleOutObservedBlocks::mutate(|observed| {
+                                *observed = *observed + One::one();
                             });
                         }
                     },
@@ -171,12 +316,14 @@ decl_module! {
 
                         //TODO: check foreign scale out phase
                         if observe_util == block_number{
+                            let observe_util = block_number + scale_out_observe_blocks;
                             <Self as Store>::CurrentScaleOutPhase::mutate(|orig| {
                                 *orig = Some(ScaleOutPhase::Ready{
-                                    observe_util: block_number + scale_out_observe_blocks,
+                                    observe_util,
                                     shard_num: target_shard_num,
                                 });
                             });
+                            Self::deposit_event(RawEvent::ScaleOutReady(target_shard_num, observe_util));
                         }
 
                     },
@@ -191,6 +338,7 @@ decl_module! {
                                     shard_count: scale_out_shard_count,
                                 });
                             });
+                            Self::deposit_event(RawEvent::ScaleOutCommitting(scale_out_shard_count));
                         }
                     },
                     ScaleOutPhase::Commiting{shard_count} => {
@@ -201,6 +349,7 @@ decl_module! {
                                 shard_count: shard_count,
                             });
                         });
+                        Self::deposit_event(RawEvent::ScaleOutCommitted(target_shard_num, shard_count));
                     },
                     ScaleOutPhase::Committed{..} => {
 
@@ -211,6 +360,26 @@ decl_module! {
                 }
             }
 
+            Self::advance_scale_in(&info, block_number, scale_out_observe_blocks);
+
+        }
+
+        /// Change observe window of scale out phases,
+        /// phases in progress keep the observe_util already computed
+        #[weight = 0]
+        fn set_scale_out_observe_blocks(origin, blocks: T::BlockNumber) {
+            ensure_root(origin)?;
+            ensure!(
+                blocks >= Self::min_scale_out_observe_blocks(),
+                "Scale out observe blocks is less than min scale out observe blocks"
+            );
+
+            <Self as Store>::ScaleOutObserveBlocks::put(blocks);
+            Self::deposit_event(RawEvent::ScaleOutObserveBlocksChanged(blocks));
+        }
+
+        fn on_runtime_upgrade() -> Weight {
+            migration::migrate::<T>()
         }
 
         fn on_finalize(_block_number: T::BlockNumber) {
@@ -222,6 +391,10 @@ decl_module! {
             if let Some(scale_out_phase) = Self::current_scale_out_phase() {
                 Self::deposit_log(RawLog::ScaleOutPhase(scale_out_phase));
             }
+
+            if let Some(scale_in_phase) = Self::current_scale_in_phase() {
+                Self::deposit_log(RawLog::ScaleInPhase(scale_in_phase));
+            }
         }
     }
 }
@@ -231,6 +404,168 @@ impl<T: Trait> Module<T> {
 	fn deposit_log(log: Log<T>) {
 		<system::Module<T>>::deposit_log(<T as Trait>::Log::from(log).into());
 	}
+
+	/// Check scale out of `info` to `target` can start
+	fn check_scale_out(info: &ShardInfo<T::ShardNum>, target: T::ShardNum) -> Result<(), &'static str> {
+		if Self::current_scale_in_phase().is_some() {
+			return Err("Scale out conflicts with scale in");
+		}
+		if !Self::is_valid_scale_out_target(info.num, info.count, target) {
+			return Err("Scale out target is not reachable from genesis sharding count");
+		}
+		Ok(())
+	}
+
+	/// Check scale in of `info` to `dest` can start
+	fn check_scale_in(info: &ShardInfo<T::ShardNum>, dest: T::ShardNum) -> Result<(), &'static str> {
+		if info.scale_out.is_some() || Self::current_scale_out_phase().is_some() {
+			return Err("Scale in conflicts with scale out");
+		}
+		if !Self::is_valid_scale_in_target(info.num, info.count, dest) {
+			return Err("Scale in target is not the merged shard");
+		}
+		Ok(())
+	}
+
+	/// Check `target` is a shard split from `num` when doubling `count`,
+	/// where `count` itself is reachable by doubling genesis sharding count
+	fn is_valid_scale_out_target(num: T::ShardNum, count: T::ShardNum, target: T::ShardNum) -> bool {
+		let mut reachable = Self::genesis_sharding_count();
+		if reachable.is_zero() {
+			return false;
+		}
+		while reachable < count {
+			reachable = match reachable.checked_add(&reachable) {
+				Some(next) => next,
+				None => return false,
+			};
+		}
+		if reachable != count {
+			return false;
+		}
+		match count.checked_add(&count) {
+			Some(scaled_count) => target < scaled_count && target % count == num,
+			None => false,
+		}
+	}
+
+	/// Check shard info of sharding inherent is consistent
+	fn check_shard_info(info: &ShardInfo<T::ShardNum>) -> Result<(), &'static str> {
+		if info.count.is_zero() {
+			return Err("Shard count must not be zero");
+		}
+		if info.num >= info.count {
+			return Err("Shard num must be less than shard count");
+		}
+		if !Self::is_power_of_two(info.count) {
+			return Err("Shard count must be a power of two");
+		}
+		if let Some(scale_out) = info.scale_out.as_ref() {
+			match info.count.checked_add(&info.count) {
+				Some(scaled_count) if scale_out.shard_num < scaled_count => {},
+				_ => return Err("Scale out shard num must be less than scaled out shard count"),
+			}
+		}
+		Ok(())
+	}
+
+	fn is_power_of_two(n: T::ShardNum) -> bool {
+		let two = T::ShardNum::from(2u8);
+		let mut n = n;
+		while n > One::one() {
+			if n % two != Zero::zero() {
+				return false;
+			}
+			n = n / two;
+		}
+		n == One::one()
+	}
+
+	/// Check `dest` is the shard `num` merges into when halving `count`,
+	/// no merge goes below genesis sharding count
+	fn is_valid_scale_in_target(num: T::ShardNum, count: T::ShardNum, dest: T::ShardNum) -> bool {
+		let two = T::ShardNum::from(2u8);
+		if count % two != Zero::zero() {
+			return false;
+		}
+		let half = count / two;
+		half >= Self::genesis_sharding_count() && dest == num % half
+	}
+
+	/// Move scale in to next phase, phases advance when observe window ends
+	fn advance_scale_in(info: &ShardInfo<T::ShardNum>, block_number: T::BlockNumber, observe_blocks: T::BlockNumber) {
+		let next = match Self::current_scale_in_phase() {
+			None => match info.scale_in.as_ref() {
+				Some(scale_in) => Some(ScaleInPhase::Started {
+					observe_util: block_number + observe_blocks,
+					source: info.num,
+					dest: scale_in.shard_num,
+				}),
+				None => return,
+			},
+			Some(ScaleInPhase::Started { observe_util, source, dest }) => {
+				if observe_util != block_number {
+					return;
+				}
+				Some(ScaleInPhase::Ready {
+					observe_util: block_number + observe_blocks,
+					source,
+					dest,
+				})
+			}
+			Some(ScaleInPhase::Ready { observe_util, source, dest }) => {
+				if observe_util != block_number {
+					return;
+				}
+				Some(ScaleInPhase::Committed { source, dest })
+			}
+			Some(ScaleInPhase::Committed { .. }) => None,
+		};
+		<Self as Store>::CurrentScaleInPhase::mutate(|orig| {
+			*orig = next;
+		});
+	}
+
+	/// Whether blocks from scaled out nodes reach ready threshold in observe window
+	fn is_scale_out_ready() -> bool {
+		let threshold: T::BlockNumber = (Self::scale_out_ready_threshold().min(100) as u32).into();
+		let observed = Self::scale_out_observed_blocks();
+		// windows started before the length was kept use the current one
+		let observe_blocks = Self::scale_out_observing_blocks().unwrap_or_else(Self::scale_out_observe_blocks);
+		observed * 100u32.into() >= observe_blocks * threshold
+	}
+
+	/// Progress 0 to 100 of the scale out in progress, `None` if not scaling out.
+	///
+	/// Started, NativeReady, Ready and Commiting each take a quarter,
+	/// observing phases advance within their quarter by blocks observed.
+	pub fn scale_out_progress() -> Option<u8> {
+		let (base, observe_util) = match Self::current_scale_out_phase()? {
+			ScaleOutPhase::Started { observe_util, .. } => (0u32, observe_util),
+			ScaleOutPhase::NativeReady { observe_util, .. } => (25, observe_util),
+			ScaleOutPhase::Ready { observe_util, .. } => (50, observe_util),
+			ScaleOutPhase::Commiting { .. } => return Some(75),
+			ScaleOutPhase::Committed { .. } => return Some(100),
+		};
+		let observe_blocks = Self::scale_out_observe_blocks();
+		if observe_blocks.is_zero() {
+			return Some(base as u8);
+		}
+		let block_number = <system::Module<T>>::block_number();
+		let remaining = observe_util.saturating_sub(block_number).min(observe_blocks);
+		let observed: u32 = ((observe_blocks - remaining) * 25u32.into() / observe_blocks).unique_saturated_into();
+		Some((base + observed.min(24)) as u8)
+	}
+
+	/// Target shard num of the scale out phase in progress, if any
+	fn in_progress_scale_out_target() -> Option<T::ShardNum> {
+		match Self::current_scale_out_phase() {
+			Some(ScaleOutPhase::Started { shard_num, .. })
+			| Some(ScaleOutPhase::NativeReady { shard_num, .. })
+			| Some(ScaleOutPhase::Ready { shard_num, .. }) => Some(shard_num),
+			_ => None,
+		}
+	}
 }
 
 impl<T: Trait> ShardingInfo<T::ShardNum> for Module<T> {
@@ -238,17 +573,19 @@ impl<T: Trait> ShardingInfo<T::ShardNum> for Module<T> {
 		Self::genesis_sharding_count()
 	}
 
+	/// Falls back to shard 0 before sharding inherent of current block is set
 	fn get_curr_shard() -> Option<T::ShardNum> {
 		Some(Self::current_shard_info()
-			.expect("shard info must be ready for runtime modules")
-			.num
+			.map(|info| info.num)
+			.unwrap_or_else(Zero::zero)
 		)
 	}
 
+	/// Falls back to genesis sharding count before sharding inherent of current block is set
 	fn get_shard_count() -> T::ShardNum {
 		Self::current_shard_info()
-			.expect("shard info must be ready for runtime modules")
-			.count
+			.map(|info| info.count)
+			.unwrap_or_else(Self::genesis_sharding_count)
 	}
 }
 
@@ -264,8 +601,12 @@ impl<T: Trait> ProvideInherent for Module<T> {
 		Some(Call::set_shard_info(data))
 	}
 
-	fn check_inherent(_: &Self::Call, _: &InherentData) -> Result<(), Self::Error> {
-		Ok(())
+	fn check_inherent(call: &Self::Call, _: &InherentData) -> Result<(), Self::Error> {
+		match call {
+			Call::set_shard_info(info) => Self::check_shard_info(info)
+				.map_err(|e| sp_inherents::Error::from(e).into()),
+			_ => Ok(()),
+		}
 	}
 }
 
@@ -0,0 +1,57 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Storage migrations for the module.
+
+use codec::Decode;
+use frame_support::{
+	storage::StorageValue,
+	traits::Get,
+	weights::Weight,
+};
+use yp_sharding::{ScaleOut, ShardInfo};
+
+use crate::{CurrentShardInfo, Module, Releases, StorageVersion, Trait};
+
+/// `ShardInfo` layout of V0 and V1, before scale in
+#[derive(Decode)]
+struct ShardInfoV1<N> {
+	num: N,
+	count: N,
+	scale_out: Option<ScaleOut<N>>,
+}
+
+/// Migrate storage to the latest layout, returns weight consumed
+pub fn migrate<T: Trait>() -> Weight {
+	match Module::<T>::storage_version() {
+		Releases::V0 | Releases::V1 => {
+			// V1 kept the V0 layout, V2 appends scale_in to CurrentShardInfo,
+			// CurrentScaleOutPhase is unchanged
+			let _ = CurrentShardInfo::<T>::translate(|old: Option<ShardInfoV1<T::ShardNum>>| {
+				old.map(|old| ShardInfo {
+					num: old.num,
+					count: old.count,
+					scale_out: old.scale_out,
+					scale_in: None,
+				})
+			});
+			StorageVersion::put(Releases::V2);
+			T::DbWeight::get().reads_writes(2, 2)
+		}
+		Releases::V2 => T::DbWeight::get().reads(1),
+	}
+}
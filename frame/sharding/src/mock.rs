@@ -0,0 +1,125 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Test utilities
+
+use frame_support::{impl_outer_event, impl_outer_origin, parameter_types, weights::Weight};
+use sp_core::H256;
+use sp_runtime::{
+	Perbill,
+	generic::DigestItem,
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+};
+use yp_sharding::{ScaleIn, ScaleOut, ShardInfo};
+
+use crate::{GenesisConfig, Module, RawLog, Trait};
+use codec::Encode;
+
+impl_outer_origin! {
+	pub enum Origin for Test where system = frame_system {}
+}
+
+mod sharding {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Test {
+		frame_system<T>,
+		sharding<T>,
+	}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+
+impl frame_system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type ModuleToIndex = ();
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+}
+
+impl From<RawLog<u16, u64>> for DigestItem<H256> {
+	fn from(log: RawLog<u16, u64>) -> Self {
+		DigestItem::Other(log.encode())
+	}
+}
+
+impl Trait for Test {
+	type ShardNum = u16;
+	type Log = DigestItem<H256>;
+	type Event = TestEvent;
+}
+
+pub type System = frame_system::Module<Test>;
+pub type Sharding = Module<Test>;
+
+pub fn new_test_ext(genesis_sharding_count: u16, scale_out_observe_blocks: u64) -> sp_io::TestExternalities {
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisConfig::<Test> {
+		genesis_sharding_count,
+		scale_out_observe_blocks,
+		min_scale_out_observe_blocks: 1,
+		scale_out_ready_threshold: 0,
+	}.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
+
+pub fn shard_info(num: u16, count: u16, scale_out: Option<u16>) -> ShardInfo<u16> {
+	ShardInfo {
+		num,
+		count,
+		scale_out: scale_out.map(|shard_num| ScaleOut { shard_num }),
+		scale_in: None,
+	}
+}
+
+pub fn scale_in_info(num: u16, count: u16, scale_in: Option<u16>) -> ShardInfo<u16> {
+	ShardInfo {
+		num,
+		count,
+		scale_out: None,
+		scale_in: scale_in.map(|shard_num| ScaleIn { shard_num }),
+	}
+}
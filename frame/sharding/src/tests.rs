@@ -0,0 +1,454 @@
+// Copyright (C) 2019 Yee Foundation.
+//
+// This file is part of YeeChain.
+//
+// YeeChain is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// YeeChain is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for the module.
+
+use codec::Encode;
+use frame_support::{assert_noop, assert_ok, storage::{StorageValue, unhashed}, traits::{OnFinalize, OnRuntimeUpgrade}};
+use sp_inherents::{InherentData, ProvideInherent};
+use sp_runtime::{DispatchError, generic::DigestItem};
+use yp_sharding::{ScaleOut, ShardInfo, ShardingInfo};
+
+use crate::{
+	Call, CurrentScaleOutPhase, CurrentShardInfo, GenesisConfig, RawEvent, RawLog, Releases, ScaleInPhase,
+	ScaleOutPhase, ScaleOutReadyThreshold, StorageVersion,
+};
+use crate::mock::{new_test_ext, Origin, scale_in_info, Sharding, shard_info, System, Test, TestEvent};
+
+#[test]
+fn conflicting_scale_out_is_ignored() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::Started { observe_util: 4, shard_num: 3 }));
+
+		// a second scale out with another target while the first is in progress
+		System::set_block_number(2);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(1))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::Started { observe_util: 4, shard_num: 3 }));
+
+		System::set_block_number(4);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(1))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::NativeReady { observe_util: 7, shard_num: 3 }));
+	});
+}
+
+#[test]
+fn reachable_scale_out_target_is_accepted() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(0, 2, Some(2))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::Started { observe_util: 4, shard_num: 2 }));
+	});
+
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(5, 8, Some(13))));
+	});
+}
+
+/// Shard info is stored without scale out and scale in, no phase starts
+fn assert_scale_ignored(info: ShardInfo<u16>) {
+	let (num, count) = (info.num, info.count);
+	assert_ok!(Sharding::set_shard_info(Origin::none(), info));
+	assert_eq!(Sharding::current_shard_info(), Some(shard_info(num, count, None)));
+}
+
+#[test]
+fn unreachable_scale_out_target_is_ignored() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		// not a shard split from shard 1
+		assert_scale_ignored(shard_info(1, 2, Some(2)));
+		// beyond the next doubling
+		assert_scale_ignored(shard_info(1, 2, Some(5)));
+		// shard count 3 never comes from doubling genesis count 2
+		assert_scale_ignored(shard_info(1, 3, Some(4)));
+		assert_eq!(Sharding::current_scale_out_phase(), None);
+	});
+}
+
+#[test]
+fn ignored_scale_out_does_not_wedge_phases() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+
+		// invalid target at the end of observe window, phase still advances
+		System::set_block_number(4);
+		assert_scale_ignored(shard_info(1, 2, Some(2)));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::NativeReady { observe_util: 7, shard_num: 3 }));
+	});
+}
+
+#[test]
+fn scale_out_progress_increases() {
+	new_test_ext(2, 3).execute_with(|| {
+		assert_eq!(Sharding::scale_out_progress(), None);
+
+		let mut progress = vec![];
+		for n in 1..=12 {
+			System::set_block_number(n);
+			assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+			progress.push(Sharding::scale_out_progress());
+		}
+		assert_eq!(progress, vec![
+			Some(0), Some(8), Some(16),
+			Some(25), Some(33), Some(41),
+			Some(50), Some(58), Some(66),
+			Some(75), Some(100), None,
+		]);
+	});
+}
+
+#[test]
+fn scale_out_below_ready_threshold_stays_started() {
+	new_test_ext(2, 3).execute_with(|| {
+		ScaleOutReadyThreshold::put(60);
+
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		for n in 2..4 {
+			System::set_block_number(n);
+			assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, None)));
+		}
+		assert_eq!(Sharding::scale_out_observed_blocks(), 1);
+
+		// 1 of 3 blocks observed, window extended
+		System::set_block_number(4);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, None)));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::Started { observe_util: 7, shard_num: 3 }));
+		assert_eq!(Sharding::scale_out_observed_blocks(), 0);
+	});
+}
+
+#[test]
+fn scale_out_at_ready_threshold_advances() {
+	new_test_ext(2, 3).execute_with(|| {
+		ScaleOutReadyThreshold::put(60);
+
+		for n in 1..3 {
+			System::set_block_number(n);
+			assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		}
+		System::set_block_number(3);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, None)));
+		assert_eq!(Sharding::scale_out_observed_blocks(), 2);
+
+		// 2 of 3 blocks observed
+		System::set_block_number(4);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, None)));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::NativeReady { observe_util: 7, shard_num: 3 }));
+		assert_eq!(Sharding::scale_out_observed_blocks(), 0);
+	});
+}
+
+/// put shard info in V0 and V1 layout, without scale_in
+fn put_legacy_shard_info(num: u16, count: u16, scale_out: Option<u16>) {
+	let legacy = (num, count, scale_out.map(|shard_num| ScaleOut { shard_num }));
+	unhashed::put_raw(&<CurrentShardInfo<Test> as StorageValue<_>>::hashed_key(), &legacy.encode());
+}
+
+#[test]
+fn migration_from_v0_preserves_values() {
+	new_test_ext(2, 3).execute_with(|| {
+		assert_eq!(Sharding::storage_version(), Releases::V2);
+
+		// chain started before storage versioning
+		StorageVersion::kill();
+		assert_eq!(Sharding::storage_version(), Releases::V0);
+		put_legacy_shard_info(1, 2, Some(3));
+		let phase = ScaleOutPhase::Ready { observe_util: 10, shard_num: 3 };
+		CurrentScaleOutPhase::<Test>::put(phase.clone());
+
+		<Sharding as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+		assert_eq!(Sharding::storage_version(), Releases::V2);
+		assert_eq!(Sharding::current_shard_info(), Some(shard_info(1, 2, Some(3))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(phase));
+
+		// later upgrades keep values too
+		<Sharding as OnRuntimeUpgrade>::on_runtime_upgrade();
+		assert_eq!(Sharding::storage_version(), Releases::V2);
+		assert_eq!(Sharding::current_shard_info(), Some(shard_info(1, 2, Some(3))));
+	});
+}
+
+#[test]
+fn migration_from_v1_adds_scale_in() {
+	new_test_ext(2, 3).execute_with(|| {
+		StorageVersion::put(Releases::V1);
+		put_legacy_shard_info(3, 4, None);
+		// legacy layout does not decode as V2
+		assert_eq!(Sharding::current_shard_info(), None);
+
+		<Sharding as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+		assert_eq!(Sharding::storage_version(), Releases::V2);
+		assert_eq!(Sharding::current_shard_info(), Some(shard_info(3, 4, None)));
+	});
+}
+
+fn scale_out_to_committing(target: u16) -> Option<ScaleOutPhase<u64, u16>> {
+	for n in 1..=4 {
+		System::set_block_number(n);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(target))));
+	}
+	Sharding::current_scale_out_phase()
+}
+
+#[test]
+fn scale_out_doubles_shard_count() {
+	new_test_ext(2, 1).execute_with(|| {
+		assert_eq!(scale_out_to_committing(3), Some(ScaleOutPhase::Commiting { shard_count: 4 }));
+	});
+}
+
+#[test]
+fn scale_in_full_cycle() {
+	new_test_ext(2, 1).execute_with(|| {
+		let phases = vec![
+			Some(ScaleInPhase::Started { observe_util: 2, source: 3, dest: 1 }),
+			Some(ScaleInPhase::Ready { observe_util: 3, source: 3, dest: 1 }),
+			Some(ScaleInPhase::Committed { source: 3, dest: 1 }),
+			None,
+		];
+		for (n, expected) in (1..=4).zip(phases) {
+			System::set_block_number(n);
+			assert_ok!(Sharding::set_shard_info(Origin::none(), scale_in_info(3, 4, Some(1))));
+			assert_eq!(Sharding::current_scale_in_phase(), expected);
+
+			<Sharding as OnFinalize<u64>>::on_finalize(n);
+			if let Some(phase) = expected {
+				let log = DigestItem::Other(RawLog::<u16, u64>::ScaleInPhase(phase).encode());
+				assert!(System::digest().logs().contains(&log));
+			}
+		}
+	});
+}
+
+#[test]
+fn invalid_scale_in_is_ignored() {
+	new_test_ext(2, 1).execute_with(|| {
+		System::set_block_number(1);
+		// shard 3 of 4 merges into 1, not 0
+		assert_scale_ignored(scale_in_info(3, 4, Some(0)));
+		// no merge below genesis sharding count
+		assert_scale_ignored(scale_in_info(1, 2, Some(0)));
+		assert_eq!(Sharding::current_scale_in_phase(), None);
+
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		// conflicts with scale out in progress
+		assert_scale_ignored(scale_in_info(3, 4, Some(1)));
+		assert_eq!(Sharding::current_scale_in_phase(), None);
+	});
+}
+
+fn observe_blocks_genesis(scale_out_observe_blocks: u64, min_scale_out_observe_blocks: u64) -> GenesisConfig<Test> {
+	GenesisConfig::<Test> {
+		genesis_sharding_count: 2,
+		scale_out_observe_blocks,
+		min_scale_out_observe_blocks,
+		scale_out_ready_threshold: 0,
+	}
+}
+
+#[test]
+#[should_panic(expected = "Scale out observe blocks is less than min scale out observe blocks")]
+fn zero_scale_out_observe_blocks_is_rejected() {
+	let _ = observe_blocks_genesis(0, 1).build_storage();
+}
+
+#[test]
+#[should_panic(expected = "Scale out observe blocks is less than min scale out observe blocks")]
+fn too_small_scale_out_observe_blocks_is_rejected() {
+	let _ = observe_blocks_genesis(5, 10).build_storage();
+}
+
+#[test]
+#[should_panic(expected = "Genesis sharding count must be a power of two")]
+fn genesis_sharding_count_not_power_of_two_is_rejected() {
+	let mut config = observe_blocks_genesis(3, 1);
+	config.genesis_sharding_count = 3;
+	let _ = config.build_storage();
+}
+
+#[test]
+fn genesis_sharding_count_power_of_two_is_accepted() {
+	for count in &[1u16, 2, 4] {
+		let mut config = observe_blocks_genesis(3, 1);
+		config.genesis_sharding_count = *count;
+		let t = config.build_storage().unwrap();
+		sp_io::TestExternalities::from(t).execute_with(|| {
+			System::set_block_number(1);
+			// first scale out from genesis passes the inherent check and starts
+			let info = shard_info(0, *count, Some(*count));
+			assert!(check_shard_info_inherent(info.clone()));
+			assert_ok!(Sharding::set_shard_info(Origin::none(), info));
+			assert!(Sharding::current_scale_out_phase().is_some());
+		});
+	}
+}
+
+#[test]
+fn valid_scale_out_observe_blocks_is_accepted() {
+	let t = observe_blocks_genesis(10, 10).build_storage().unwrap();
+	sp_io::TestExternalities::from(t).execute_with(|| {
+		assert_eq!(Sharding::scale_out_observe_blocks(), 10);
+		assert_eq!(Sharding::min_scale_out_observe_blocks(), 10);
+	});
+}
+
+#[test]
+fn scale_out_transitions_deposit_events() {
+	new_test_ext(2, 1).execute_with(|| {
+		let expected = vec![
+			RawEvent::ScaleOutStarted(3, 2),
+			RawEvent::ScaleOutNativeReady(3, 3),
+			RawEvent::ScaleOutReady(3, 4),
+			RawEvent::ScaleOutCommitting(4),
+			RawEvent::ScaleOutCommitted(3, 4),
+		];
+		for (n, event) in (1..=5).zip(expected) {
+			System::set_block_number(n);
+			assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+			assert_eq!(System::events().last().map(|record| record.event.clone()), Some(TestEvent::sharding(event)));
+		}
+		assert_eq!(System::events().len(), 5);
+
+		// no event when scale out phase is cleared
+		System::set_block_number(6);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(3, 4, None)));
+		assert_eq!(System::events().len(), 5);
+	});
+}
+
+#[test]
+fn sharding_info_before_inherent_falls_back_to_genesis() {
+	new_test_ext(4, 3).execute_with(|| {
+		assert_eq!(Sharding::current_shard_info(), None);
+		assert_eq!(<Sharding as ShardingInfo<u16>>::get_curr_shard(), Some(0));
+		assert_eq!(<Sharding as ShardingInfo<u16>>::get_shard_count(), 4);
+
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(3, 8, None)));
+		assert_eq!(<Sharding as ShardingInfo<u16>>::get_curr_shard(), Some(3));
+		assert_eq!(<Sharding as ShardingInfo<u16>>::get_shard_count(), 8);
+	});
+}
+
+fn check_shard_info_inherent(info: ShardInfo<u16>) -> bool {
+	Sharding::check_inherent(&Call::set_shard_info(info), &InherentData::new()).is_ok()
+}
+
+#[test]
+fn consistent_sharding_inherent_is_accepted() {
+	new_test_ext(2, 3).execute_with(|| {
+		assert!(check_shard_info_inherent(shard_info(1, 2, None)));
+		assert!(check_shard_info_inherent(shard_info(0, 1, None)));
+		assert!(check_shard_info_inherent(shard_info(1, 2, Some(3))));
+	});
+}
+
+#[test]
+fn inconsistent_sharding_inherent_is_rejected() {
+	new_test_ext(2, 3).execute_with(|| {
+		// zero count
+		assert!(!check_shard_info_inherent(shard_info(0, 0, None)));
+		// num out of count
+		assert!(!check_shard_info_inherent(shard_info(2, 2, None)));
+		// count not a power of two
+		assert!(!check_shard_info_inherent(shard_info(1, 3, None)));
+		// scale out target out of scaled out count
+		assert!(!check_shard_info_inherent(shard_info(1, 2, Some(4))));
+	});
+}
+
+#[test]
+fn current_scale_out_phase_is_primitive_type() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+
+		// what ShardingAPI::get_current_scale_out_phase returns
+		let phase: Option<yp_sharding::ScaleOutPhase<u64, u16>> = Sharding::current_scale_out_phase();
+		assert_eq!(phase, Some(yp_sharding::ScaleOutPhase::Started { observe_util: 4, shard_num: 3 }));
+	});
+}
+
+#[test]
+fn set_scale_out_observe_blocks_requires_root() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_noop!(Sharding::set_scale_out_observe_blocks(Origin::signed(1), 10), DispatchError::BadOrigin);
+		assert_noop!(Sharding::set_scale_out_observe_blocks(Origin::none(), 10), DispatchError::BadOrigin);
+		assert_noop!(
+			Sharding::set_scale_out_observe_blocks(Origin::root(), 0),
+			"Scale out observe blocks is less than min scale out observe blocks"
+		);
+
+		assert_ok!(Sharding::set_scale_out_observe_blocks(Origin::root(), 10));
+		assert_eq!(Sharding::scale_out_observe_blocks(), 10);
+		assert_eq!(
+			System::events().last().map(|record| record.event.clone()),
+			Some(TestEvent::sharding(RawEvent::ScaleOutObserveBlocksChanged(10)))
+		);
+	});
+}
+
+#[test]
+fn set_scale_out_observe_blocks_is_not_retroactive() {
+	new_test_ext(2, 3).execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		System::set_block_number(4);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::NativeReady { observe_util: 7, shard_num: 3 }));
+
+		// phase in progress keeps its observe_util
+		assert_ok!(Sharding::set_scale_out_observe_blocks(Origin::root(), 10));
+		System::set_block_number(5);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::NativeReady { observe_util: 7, shard_num: 3 }));
+
+		// next phase uses the new window
+		System::set_block_number(7);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::Ready { observe_util: 17, shard_num: 3 }));
+	});
+}
+
+#[test]
+fn set_scale_out_observe_blocks_keeps_started_window() {
+	new_test_ext(2, 3).execute_with(|| {
+		ScaleOutReadyThreshold::put(60);
+
+		for n in 1..3 {
+			System::set_block_number(n);
+			assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, Some(3))));
+		}
+		assert_ok!(Sharding::set_scale_out_observe_blocks(Origin::root(), 10));
+		System::set_block_number(3);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, None)));
+
+		// 2 of 3 blocks observed, not 2 of 10
+		System::set_block_number(4);
+		assert_ok!(Sharding::set_shard_info(Origin::none(), shard_info(1, 2, None)));
+		assert_eq!(Sharding::current_scale_out_phase(), Some(ScaleOutPhase::NativeReady { observe_util: 14, shard_num: 3 }));
+		assert_eq!(Sharding::scale_out_observing_blocks(), None);
+	});
+}
@@ -63,5 +63,16 @@ pub struct PowInfo<AccountId> {
 #[cfg_attr(feature = "std", derive(Debug, Serialize))]
 pub enum RewardCondition {
     Normal,
-    Slash,//TODO: provide slash reason
+    Slash(SlashReason),
+}
+
+/// Shard or relay violation a block is slashed for
+#[derive(Clone, PartialEq, Eq)]
+#[derive(Decode, Encode)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize))]
+pub enum SlashReason {
+    /// relay proof does not match block body
+    InvalidRelayProof,
+    /// block builds on a fork of a foreign shard
+    ForeignFork,
 }
@@ -18,6 +18,7 @@
 use yp_consensus_pow::PowTarget;
 use {
 	sp_runtime::{
+		ConsensusEngineId,
 		traits::{NumberFor, Block as BlockT},
 	},
 };
@@ -31,4 +32,8 @@ pub struct Context<Block> where
 	pub genesis_target_block_time: u64,
 	pub genesis_shard_count: u16,
 	pub genesis_scale_out_observe_blocks: NumberFor<Block>,
+	/// engine id of POW seal digest, `YEE_POW_ENGINE_ID` unless overridden by chain spec
+	pub pow_engine_id: ConsensusEngineId,
+	/// max extrinsics in a block body an extrinsic proof is generated for
+	pub max_proof_extrinsics: usize,
 }
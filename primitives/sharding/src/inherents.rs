@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "std")]
+use std::{collections::VecDeque, sync::{Arc, Mutex}};
+
 use codec::Decode;
 use sp_inherents::{InherentData, InherentIdentifier, ProvideInherentData};
 
-use crate::{ScaleOut, ShardInfo};
+use crate::{ScaleIn, ScaleOut, ShardInfo};
 
 pub const INHERENT_IDENTIFIER: InherentIdentifier = *b"YeeShard";
 
@@ -37,18 +40,73 @@ impl YeeShardInherentData for InherentData {
 	}
 }
 
+/// Starts scale out when average block fullness over the last `window` blocks exceeds `threshold`.
+///
+/// Fullness is in percent of the block limit, fed by the author for each imported block.
+#[cfg(feature = "std")]
+pub struct LoadScaleOutTrigger {
+	threshold: u8,
+	window: usize,
+	fullness: Mutex<VecDeque<u8>>,
+}
+
+#[cfg(feature = "std")]
+impl LoadScaleOutTrigger {
+	pub fn new(threshold: u8, window: usize) -> Self {
+		Self {
+			threshold,
+			window,
+			fullness: Mutex::new(VecDeque::with_capacity(window)),
+		}
+	}
+
+	/// record fullness of an imported block
+	pub fn on_block(&self, fullness: u8) {
+		let mut recent = self.fullness.lock().expect("qed");
+		recent.push_back(fullness.min(100));
+		while recent.len() > self.window {
+			recent.pop_front();
+		}
+	}
+
+	/// true if the window is full and its average fullness exceeds the threshold
+	pub fn triggered(&self) -> bool {
+		let recent = self.fullness.lock().expect("qed");
+		if self.window == 0 || recent.len() < self.window {
+			return false;
+		}
+		let total: usize = recent.iter().map(|x| *x as usize).sum();
+		total / self.window > self.threshold as usize
+	}
+}
+
 #[cfg(feature = "std")]
 pub struct InherentDataProvider {
 	shard_info: ShardInfo<u16>,
+	/// load trigger and the shard num to scale out to
+	load_scale_out: Option<(Arc<LoadScaleOutTrigger>, u16)>,
 }
 
 #[cfg(feature = "std")]
 impl InherentDataProvider {
 	pub fn new(num: u16, count: u16, scale_out: Option<ScaleOut<u16>>) -> Self {
 		Self {
-			shard_info: ShardInfo { num, count, scale_out },
+			shard_info: ShardInfo { num, count, scale_out, scale_in: None },
+			load_scale_out: None,
 		}
 	}
+
+	/// start scale out to `shard_num` once `trigger` fires, unless scale out is configured already
+	pub fn with_load_scale_out(mut self, trigger: Arc<LoadScaleOutTrigger>, shard_num: u16) -> Self {
+		self.load_scale_out = Some((trigger, shard_num));
+		self
+	}
+
+	/// request merging this shard into `scale_in`
+	pub fn with_scale_in(mut self, scale_in: Option<ScaleIn<u16>>) -> Self {
+		self.shard_info.scale_in = scale_in;
+		self
+	}
 }
 
 #[cfg(feature = "std")]
@@ -58,10 +116,46 @@ impl ProvideInherentData for InherentDataProvider {
 	}
 
 	fn provide_inherent_data(&self, inherent_data: &mut InherentData) -> Result<(), sp_inherents::Error> {
-		inherent_data.put_data(INHERENT_IDENTIFIER, &self.shard_info)
+		match self.load_scale_out {
+			Some((ref trigger, shard_num)) if self.shard_info.scale_out.is_none() && trigger.triggered() => {
+				let mut shard_info = self.shard_info.clone();
+				shard_info.scale_out = Some(ScaleOut { shard_num });
+				inherent_data.put_data(INHERENT_IDENTIFIER, &shard_info)
+			}
+			_ => inherent_data.put_data(INHERENT_IDENTIFIER, &self.shard_info),
+		}
 	}
 
 	fn error_to_string(&self, error: &[u8]) -> Option<String> {
 		sp_inherents::Error::decode(&mut &error[..]).map(|e| e.into_string()).ok()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_load_scale_out() {
+		let trigger = Arc::new(LoadScaleOutTrigger::new(80, 3));
+		let provider = InherentDataProvider::new(1, 4, None).with_load_scale_out(trigger.clone(), 5);
+		let scale_out = || {
+			let mut data = InherentData::new();
+			provider.provide_inherent_data(&mut data).unwrap();
+			data.yee_shard_inherent_data().unwrap().scale_out
+		};
+
+		trigger.on_block(90);
+		trigger.on_block(95);
+		// window not filled yet
+		assert_eq!(scale_out(), None);
+
+		trigger.on_block(85);
+		assert_eq!(scale_out(), Some(ScaleOut { shard_num: 5 }));
+
+		// load drops below threshold
+		trigger.on_block(10);
+		trigger.on_block(20);
+		assert_eq!(scale_out(), None);
+	}
+}
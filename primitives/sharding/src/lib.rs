@@ -49,6 +49,10 @@ decl_runtime_apis! {
         fn get_shard_count() -> u16;
         /// get scale_out_observe_blocks
         fn get_scale_out_observe_blocks() -> NumberFor<Block>;
+        /// get progress 0 to 100 of the scale out in progress
+        fn scale_out_progress() -> Option<u8>;
+        /// get scale out phase of current block, None if no scale out in progress
+        fn get_current_scale_out_phase() -> Option<ScaleOutPhase<NumberFor<Block>, u16>>;
     }
 }
 
@@ -59,6 +63,7 @@ pub struct ShardInfo<N> {
     pub num: N,
     pub count: N,
     pub scale_out: Option<ScaleOut<N>>,
+    pub scale_in: Option<ScaleIn<N>>,
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -67,3 +72,103 @@ pub struct ShardInfo<N> {
 pub struct ScaleOut<N> {
     pub shard_num: N,
 }
+
+/// JSON is internally tagged by `phase`, e.g. `{"phase":"started","observe_util":10,"shard_num":5}`
+#[derive(Clone, PartialEq, Eq)]
+#[derive(Decode, Encode)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize))]
+#[cfg_attr(feature = "std", serde(tag = "phase", rename_all = "snake_case"))]
+pub enum ScaleOutPhase<BlockNumber, ShardNum> {
+    Started {
+        observe_util: BlockNumber,
+        shard_num: ShardNum,
+    },
+    NativeReady {
+        observe_util: BlockNumber,
+        shard_num: ShardNum,
+    },
+    Ready {
+        observe_util: BlockNumber,
+        shard_num: ShardNum,
+    },
+    #[cfg_attr(feature = "std", serde(rename = "committing"))]
+    Commiting {
+        shard_count: ShardNum,
+    },
+    Committed {
+        shard_num: ShardNum,
+        shard_count: ShardNum,
+    },
+}
+
+/// Shard merge, `shard_num` is the shard merged into
+#[derive(Clone, PartialEq, Eq)]
+#[derive(Decode, Encode)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize))]
+pub struct ScaleIn<N> {
+    pub shard_num: N,
+}
+
+/// Stable JSON shape of `ShardInfo` for RPC clients
+#[cfg(feature = "std")]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct ShardInfoJson<N> {
+    pub num: N,
+    pub count: N,
+    pub scaling_out: bool,
+    pub scale_out_target: Option<N>,
+}
+
+#[cfg(feature = "std")]
+impl<N: Clone> ShardInfo<N> {
+    /// flatten into the stable JSON shape
+    pub fn to_json(&self) -> ShardInfoJson<N> {
+        ShardInfoJson {
+            num: self.num.clone(),
+            count: self.count.clone(),
+            scaling_out: self.scale_out.is_some(),
+            scale_out_target: self.scale_out.as_ref().map(|x| x.shard_num.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ScaleOut, ScaleOutPhase, ShardInfo};
+
+    #[test]
+    fn test_shard_info_json() {
+        let info = ShardInfo { num: 1u16, count: 4u16, scale_out: None, scale_in: None };
+        assert_eq!(
+            serde_json::to_string(&info.to_json()).unwrap(),
+            r#"{"num":1,"count":4,"scaling_out":false,"scale_out_target":null}"#
+        );
+
+        let info = ShardInfo { num: 1u16, count: 4u16, scale_out: Some(ScaleOut { shard_num: 5u16 }), scale_in: None };
+        assert_eq!(
+            serde_json::to_string(&info.to_json()).unwrap(),
+            r#"{"num":1,"count":4,"scaling_out":true,"scale_out_target":5}"#
+        );
+    }
+
+    #[test]
+    fn test_scale_out_phase_json() {
+        let phase = ScaleOutPhase::<u64, u16>::Started { observe_util: 10, shard_num: 5 };
+        assert_eq!(
+            serde_json::to_string(&phase).unwrap(),
+            r#"{"phase":"started","observe_util":10,"shard_num":5}"#
+        );
+
+        let phase = ScaleOutPhase::<u64, u16>::Commiting { shard_count: 8 };
+        assert_eq!(
+            serde_json::to_string(&phase).unwrap(),
+            r#"{"phase":"committing","shard_count":8}"#
+        );
+
+        let phase = ScaleOutPhase::<u64, u16>::Committed { shard_num: 5, shard_count: 8 };
+        assert_eq!(
+            serde_json::to_string(&phase).unwrap(),
+            r#"{"phase":"committed","shard_num":5,"shard_count":8}"#
+        );
+    }
+}
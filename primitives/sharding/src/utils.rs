@@ -16,6 +16,7 @@
 // along with YeeChain.  If not, see <https://www.gnu.org/licenses/>.
 
 use codec::Codec;
+use sp_runtime::sp_std::vec::Vec;
 
 pub fn shard_num_for<T: Codec>(t: &T, shard_count: u16) -> Option<u16> {
 
@@ -40,6 +41,47 @@ pub fn shard_num_for_bytes(bytes: &[u8], shard_count: u16) -> Option<u16> {
     })
 }
 
+/// Shards other than `shard_num` out of `shard_count`.
+///
+/// Shards are always in ascending numeric order, relay routing and
+/// connection logic rely on the same order across nodes.
+pub fn foreign_shards(shard_num: u16, shard_count: u16) -> Vec<u16> {
+    (0..shard_count).filter(|&num| num != shard_num).collect()
+}
+
+/// Shard the account on `shard_num` of `shard_count` lands on after one scale out,
+/// i.e. shard count doubled.
+pub fn post_scale_shard(bytes: &[u8], shard_num: u16, shard_count: u16) -> Option<u16> {
+
+    let len = bytes.len();
+
+    if len < 2 || shard_num >= shard_count {
+        return None;
+    }
+
+    let digits = get_digits(shard_count)?;
+    shard_count.checked_mul(2)?;
+
+    let a = u16::from_be_bytes([bytes[len-2], bytes[len-1]]);
+    let bit = (a >> digits) & 1;
+    Some(shard_num + bit * shard_count)
+}
+
+/// Shard the account lands on after `doublings` scale outs from `genesis_count` shards.
+pub fn shard_after_scale_outs(bytes: &[u8], genesis_count: u16, doublings: u32) -> Option<u16> {
+
+    if doublings >= 16 {
+        return None;
+    }
+
+    let shard_count = (genesis_count as u32) << doublings;
+    if shard_count > u16::max_value() as u32 {
+        return None;
+    }
+
+    shard_num_for_bytes(bytes, shard_count as u16)
+}
+
 fn get_digits(shard_count: u16) -> Option<u16> {
     if shard_count == 0{
         return None;
@@ -79,6 +121,8 @@ mod tests {
     use schnorrkel::Keypair;
     use crate::utils::shard_num_for;
     use crate::utils::shard_num_for_bytes;
+    use crate::utils::foreign_shards;
+    use crate::utils::{post_scale_shard, shard_after_scale_outs};
     use crate::utils::log2;
     use crate::utils::pow2;
 
@@ -189,6 +233,42 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_foreign_shards() {
+
+        assert_eq!(foreign_shards(1, 4), vec![0, 2, 3]);
+
+        assert_eq!(foreign_shards(0, 4), vec![1, 2, 3]);
+
+        assert_eq!(foreign_shards(5, 8), vec![0, 1, 2, 3, 4, 6, 7]);
+
+        assert_eq!(foreign_shards(0, 1), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn test_shard_after_scale_outs() {
+
+        let bytes = hex::decode("d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d").unwrap();
+
+        for genesis_count in &[1u16, 2, 4] {
+            let mut shard_num = shard_num_for_bytes(&bytes, *genesis_count).unwrap();
+            let mut shard_count = *genesis_count;
+            assert_eq!(shard_after_scale_outs(&bytes, *genesis_count, 0), Some(shard_num));
+
+            for doublings in 1..=3 {
+                shard_num = post_scale_shard(&bytes, shard_num, shard_count).unwrap();
+                shard_count *= 2;
+                assert_eq!(shard_after_scale_outs(&bytes, *genesis_count, doublings), Some(shard_num));
+            }
+        }
+
+        assert_eq!(shard_after_scale_outs(&bytes, 1, 3), Some(0b101));
+
+        assert_eq!(shard_after_scale_outs(&bytes, 3, 1), None);
+
+        assert_eq!(shard_after_scale_outs(&bytes, 1024, 6), None);
+    }
+
     #[test]
     fn test_fail() {
 